@@ -0,0 +1,130 @@
+use std::{
+    env,
+    fs::{self, remove_file},
+    path::Path,
+    process::{self, Command},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    compiler::{compile, Assembler, Options},
+    golden::discover,
+    lexer::tokenize,
+};
+
+/// Optimization levels every program is measured at
+const LEVELS: [u8; 3] = [0, 1, 2];
+
+/// Binary of a program compiled at some optimization level, removed when
+/// dropped
+struct Binary(String);
+
+impl Binary {
+    fn build(path: &str, src: &str, opt_level: u8) -> Result<Self, String> {
+        let tokens = tokenize(src, path).map_err(|e| e.to_string())?;
+        let out = env::temp_dir().join(format!("lcl-bench-{}-{}", process::id(), opt_level));
+        let out = out.to_str().unwrap().to_string();
+
+        let options = Options {
+            opt_level,
+            assembler: Assembler::detect(),
+            ..Default::default()
+        };
+        let compiled = compile(&tokens, &out, &options);
+        let _ = remove_file(format!("{}.asm", out));
+        let _ = remove_file(format!("{}.s", out));
+        let _ = remove_file(format!("{}.o", out));
+        compiled.map_err(|e| e.to_string())?;
+        Ok(Self(out))
+    }
+
+    /// Output of a single run and the time it took
+    fn run(&self) -> Result<(Vec<u8>, Duration), String> {
+        let start = Instant::now();
+        let output = Command::new(&self.0).output().map_err(|e| e.to_string())?;
+        let elapsed = start.elapsed();
+        if !output.status.success() {
+            return Err(format!("program exited with {}", output.status));
+        }
+        Ok((output.stdout, elapsed))
+    }
+}
+
+impl Drop for Binary {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.0);
+    }
+}
+
+/// Median run time of `program` at every level in `LEVELS`, checking that
+/// all levels print the same output
+fn measure(program: &Path, runs: usize) -> Result<Vec<Duration>, String> {
+    let name = program.to_str().unwrap();
+    let src = fs::read_to_string(program).map_err(|e| e.to_string())?;
+
+    let mut expected = None;
+    let mut medians = Vec::new();
+    for level in LEVELS {
+        let binary = Binary::build(name, &src, level)?;
+        let mut times = Vec::new();
+        for _ in 0..runs {
+            let (output, elapsed) = binary.run()?;
+            match &expected {
+                None => expected = Some(output),
+                Some(expected) if *expected != output => {
+                    return Err(format!(
+                        "output at -O{} differs from -O{}",
+                        level, LEVELS[0]
+                    ))
+                }
+                Some(_) => {}
+            }
+            times.push(elapsed);
+        }
+        times.sort();
+        medians.push(times[times.len() / 2]);
+    }
+    Ok(medians)
+}
+
+/// Compiles every program under `path` at each optimization level and
+/// prints the median run time of `runs` runs, returns the exit status
+pub fn run(path: &Path, runs: usize) -> i32 {
+    let programs = match discover(path) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    let width = programs
+        .iter()
+        .map(|p| p.to_str().unwrap().len())
+        .max()
+        .unwrap_or(0);
+    print!("{:width$}", "", width = width);
+    for level in LEVELS {
+        print!("  {:>10}", format!("-O{}", level));
+    }
+    println!();
+
+    let mut status = 0;
+    for program in programs.iter() {
+        let name = program.to_str().unwrap();
+        match measure(program, runs.max(1)) {
+            Ok(medians) => {
+                print!("{:width$}", name, width = width);
+                for median in medians {
+                    print!("  {:>8.1}ms", median.as_secs_f64() * 1000.0);
+                }
+                println!();
+            }
+            Err(e) => {
+                println!("FAIL {}\n\t{}", name, e);
+                status = 1;
+            }
+        }
+    }
+    status
+}
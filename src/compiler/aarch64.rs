@@ -0,0 +1,291 @@
+//! GNU assembly for AArch64 Linux.
+//!
+//! The machine stack has to stay 16-byte aligned, so stack items live on a
+//! separate stack in `.bss`, growing down from `x28`. Functions keep `x27`
+//! pointing at their arguments and save it with the link register on the
+//! machine stack, callers drop the arguments and push the result from `x0`.
+//! The program registers are the callee-saved `x19` to `x26`.
+
+use std::io::{Result, Write};
+
+use super::{Options, Target};
+use crate::{
+    interpreter::REGISTERS,
+    ir::{BinOp, Function, Op, StackOp},
+    lexer::tokens::Width,
+    lib::constants::MEM_CAPACITY,
+};
+
+/// Size of the stack in 8-byte cells
+const STACK_CAPACITY: usize = 1 << 20;
+
+/// Registers holding the program registers
+const USER_REGISTERS: [&str; REGISTERS] = ["x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26"];
+
+/// Register holding the program register with the given index, indices are
+/// checked when lowering
+fn register(idx: usize) -> &'static str {
+    USER_REGISTERS[idx - 1]
+}
+
+fn push(reg: &str) -> String {
+    format!("\tstr {}, [x28, #-8]!\n", reg)
+}
+
+fn pop(reg: &str) -> String {
+    format!("\tldr {}, [x28], #8\n", reg)
+}
+
+/// Moves `value` into `reg` 16 bits at a time, skipping the chunks that are
+/// zero
+fn constant(reg: &str, value: i64) -> String {
+    let value = value as u64;
+    let mut asm = format!("\tmovz {}, #{}\n", reg, value & 0xffff);
+    for shift in [16, 32, 48] {
+        let chunk = (value >> shift) & 0xffff;
+        if chunk != 0 {
+            asm.push_str(&format!("\tmovk {}, #{}, lsl #{}\n", reg, chunk, shift));
+        }
+    }
+    asm
+}
+
+/// Restores the caller's frame and stack, leaving the arguments in place
+const LEAVE: &str = "\tmov x28, x27\n\tldp x27, x30, [sp], #16\n";
+
+/// Moves the `args` arguments on top of the stack over the arguments of the
+/// current function, drops its frame and branches to `name`, which then
+/// returns to the caller of the current function
+fn tail_call(name: &str, args: usize) -> String {
+    let mut asm = String::new();
+    for i in 0..args {
+        asm.push_str(&format!(
+            "\tldr x0, [x28, #{0}]\n\tstr x0, [x27, #{0}]\n",
+            i * 8
+        ));
+    }
+    asm.push_str(LEAVE);
+    asm.push_str(&format!("\tb {}\n", name));
+    asm
+}
+
+/// GNU assembly for AArch64 Linux
+pub struct Aarch64 {
+    mem_capacity: usize,
+    libc: bool,
+}
+
+impl Aarch64 {
+    pub fn new(options: &Options) -> Self {
+        Self {
+            mem_capacity: MEM_CAPACITY,
+            libc: options.libc,
+        }
+    }
+
+    /// Label the program starts at, libc's `_start` calls `main`
+    fn entry_point(&self) -> &'static str {
+        if self.libc {
+            "main"
+        } else {
+            "_start"
+        }
+    }
+
+    fn op_to_asm(&self, op: &Op) -> String {
+        match op {
+            Op::BinOp(op) => {
+                let (name, instr) = match op {
+                    BinOp::Add => ("Plus", "add x0, x0, x1"),
+                    BinOp::Sub => ("Minus", "sub x0, x0, x1"),
+                    BinOp::Less => ("Less", "cmp x0, x1\n\tcset x0, lt"),
+                    BinOp::Greater => ("Greater", "cmp x0, x1\n\tcset x0, gt"),
+                    BinOp::Equal => ("Equal", "cmp x0, x1\n\tcset x0, eq"),
+                    BinOp::NotEqual => ("NotEqual", "cmp x0, x1\n\tcset x0, ne"),
+                };
+                format!(
+                    "\t// {}\n{}{}\t{}\n{}",
+                    name,
+                    pop("x1"),
+                    pop("x0"),
+                    instr,
+                    push("x0")
+                )
+            }
+            Op::Print => format!("\t// Dot\n{}\tbl print\n", pop("x0")),
+            Op::Stack(StackOp::Dup) => format!("\t// DUP\n\tldr x0, [x28]\n{}", push("x0")),
+            Op::Stack(StackOp::Drop) => "\t// DROP\n\tadd x28, x28, #8\n".to_string(),
+            Op::Stack(StackOp::Swap) => {
+                "\t// SWAP\n\tldp x0, x1, [x28]\n\tstp x1, x0, [x28]\n".to_string()
+            }
+            Op::Stack(StackOp::Over) => {
+                format!("\t// OVER\n\tldr x0, [x28, #8]\n{}", push("x0"))
+            }
+            Op::Stack(StackOp::Rot) => "\t// ROT\n\tldp x0, x1, [x28]\n\tldr x2, [x28, #16]\n\tstr x1, [x28, #16]\n\tstp x2, x0, [x28]\n".to_string(),
+            Op::Label(l) => format!(".L{}:\n", l),
+            Op::Jump(l) => format!("\tb .L{}\n", l),
+            Op::Branch(l) => format!("\t// Branch\n{}\tcbz x0, .L{}\n", pop("x0"), l),
+            Op::Call {
+                name,
+                args,
+                returning,
+            } => format!(
+                "\t// Call {0}\n\tbl {0}\n\tadd x28, x28, #{1}\n{2}",
+                name,
+                args * 8,
+                if *returning { push("x0") } else { String::new() }
+            ),
+            // The machine stack is always aligned, and every register the
+            // callee may clobber is scratch between ops
+            Op::CallExtern {
+                name,
+                args,
+                returning,
+            } => {
+                let mut asm = format!("\t// Call extern {}\n", name);
+                for i in 0..*args {
+                    asm.push_str(&pop(&format!("x{}", i)));
+                }
+                asm.push_str(&format!("\tbl {}\n", name));
+                if *returning {
+                    asm.push_str(&push("x0"));
+                }
+                asm
+            }
+            Op::TailCall { name, args } => {
+                format!("\t// Tail call {}\n{}", name, tail_call(name, *args))
+            }
+            Op::PushLocal(i) => format!(
+                "\t// Push local {}\n\tldr x0, [x27, #{}]\n{}",
+                i,
+                i * 8,
+                push("x0")
+            ),
+            Op::Pick(n) => format!("\t// Pick {}\n\tldr x0, [x28, #{}]\n{}", n, n * 8, push("x0")),
+            Op::PushMem => format!(
+                "\t// MEM\n\tadrp x0, mem\n\tadd x0, x0, :lo12:mem\n{}",
+                push("x0")
+            ),
+            Op::PushConst(n) => format!("\t// Push {}\n{}{}", n, constant("x0", *n), push("x0")),
+            Op::Load(width) => {
+                let load = match width {
+                    None => "ldr x0, [x0]",
+                    Some(Width::U8) => "ldrb w0, [x0]",
+                    Some(Width::I8) => "ldrsb x0, [x0]",
+                    Some(Width::U16) => "ldrh w0, [x0]",
+                    Some(Width::I16) => "ldrsh x0, [x0]",
+                    Some(Width::U32) => "ldr w0, [x0]",
+                    Some(Width::I32) => "ldrsw x0, [x0]",
+                };
+                format!("\t// Load\n{}\t{}\n{}", pop("x0"), load, push("x0"))
+            }
+            Op::Store(width) => {
+                let store = match width.map(|w| w.bytes()) {
+                    None => "str x0, [x1]",
+                    Some(1) => "strb w0, [x1]",
+                    Some(2) => "strh w0, [x1]",
+                    Some(_) => "str w0, [x1]",
+                };
+                format!("\t// Store\n{}{}\t{}\n", pop("x0"), pop("x1"), store)
+            }
+            Op::PushReg(i) => format!("\t// Push r{}\n{}", i, push(register(*i))),
+            Op::PopReg(i) => format!("\t// Pop r{}\n{}", i, pop(register(*i))),
+        }
+    }
+}
+
+impl Target for Aarch64 {
+    fn comment(&self) -> &'static str {
+        "//"
+    }
+
+    fn function(&self, function: &Function) -> String {
+        let mut asm = format!(
+            "{}:\n\tstp x27, x30, [sp, #-16]!\n\tmov x27, x28\n",
+            function.name
+        );
+        for op in function.body.iter() {
+            asm.push_str(&self.op_to_asm(op));
+        }
+        if function.returning {
+            asm.push_str(&pop("x0"));
+        }
+        asm.push_str(LEAVE);
+        asm.push_str("\tret\n");
+        asm
+    }
+
+    fn body(&self, ops: &[Op]) -> String {
+        ops.iter().map(|op| self.op_to_asm(op)).collect()
+    }
+
+    fn headers(&self, handler: &mut dyn Write) -> Result<()> {
+        writeln!(handler, "\t.global {}", self.entry_point())?;
+        writeln!(handler, "\t.text")?;
+
+        // Print function, writes the digits of x0 backwards from the
+        // newline at the end of a buffer on the machine stack
+        writeln!(handler, "print:")?;
+        writeln!(handler, "\tsub     sp, sp, #32")?;
+        writeln!(handler, "\tmov     x6, x0")?;
+        // The magnitude of the smallest value only fits unsigned
+        writeln!(handler, "\tcmp     x0, #0")?;
+        writeln!(handler, "\tcneg    x0, x0, lt")?;
+        writeln!(handler, "\tadd     x1, sp, #31")?;
+        writeln!(handler, "\tmov     w2, #10")?;
+        writeln!(handler, "\tstrb    w2, [x1]")?;
+        writeln!(handler, "\tmov     x3, #10")?;
+        writeln!(handler, ".Lprint:")?;
+        writeln!(handler, "\tudiv    x4, x0, x3")?;
+        writeln!(handler, "\tmsub    x5, x4, x3, x0")?;
+        writeln!(handler, "\tadd     w5, w5, #48")?;
+        writeln!(handler, "\tstrb    w5, [x1, #-1]!")?;
+        writeln!(handler, "\tmov     x0, x4")?;
+        writeln!(handler, "\tcbnz    x0, .Lprint")?;
+        writeln!(handler, "\ttbz     x6, #63, .Lprint_positive")?;
+        writeln!(handler, "\tmov     w5, #45")?;
+        writeln!(handler, "\tstrb    w5, [x1, #-1]!")?;
+        writeln!(handler, ".Lprint_positive:")?;
+        writeln!(handler, "\tadd     x2, sp, #32")?;
+        writeln!(handler, "\tsub     x2, x2, x1")?;
+        writeln!(handler, "\tmov     x0, #1")?;
+        writeln!(handler, "\tmov     x8, #64")?;
+        writeln!(handler, "\tsvc     #0")?;
+        writeln!(handler, "\tadd     sp, sp, #32")?;
+        writeln!(handler, "\tret")?;
+
+        Ok(())
+    }
+
+    fn entry(&self, handler: &mut dyn Write) -> Result<()> {
+        writeln!(handler, "{}:", self.entry_point())?;
+        writeln!(handler, "\tadrp x28, stack_top")?;
+        writeln!(handler, "\tadd x28, x28, :lo12:stack_top")?;
+        for reg in USER_REGISTERS.iter() {
+            writeln!(handler, "\tmov {}, #0", reg)?;
+        }
+
+        Ok(())
+    }
+
+    fn footers(&self, handler: &mut dyn Write) -> Result<()> {
+        writeln!(handler, "\tmov x0, #0")?;
+        if self.libc {
+            // `exit` flushes the buffers of stdio
+            writeln!(handler, "\tbl exit")?;
+        } else {
+            writeln!(handler, "\tmov x8, #93")?;
+            writeln!(handler, "\tsvc #0")?;
+        }
+
+        writeln!(handler, "\t.bss")?;
+        writeln!(handler, "\t.balign 16")?;
+        writeln!(handler, "stack:")?;
+        writeln!(handler, "\t.skip {}", STACK_CAPACITY * 8)?;
+        writeln!(handler, "stack_top:")?;
+        writeln!(handler, "mem:")?;
+        writeln!(handler, "\t.skip {}", self.mem_capacity * 8)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Portable C source generated from IR.
+//!
+//! The data stack is a static `int64_t` array with `sp` pointing past its
+//! top, every function becomes a C function working on it. A function
+//! remembers where its arguments end in `fp`, reads parameters below it and
+//! removes its arguments itself before returning, pushing the result back
+//! if it has one.
+//!
+//! `mem` pushes the same address as in the interpreter, and accesses are
+//! checked against the bounds of the memory block the same way.
+
+use std::fmt::Write;
+
+use crate::{
+    interpreter::REGISTERS,
+    ir::{BinOp, Function, Module, Op, StackOp},
+    lexer::tokens::Width,
+    lib::constants::{MEM_BASE, MEM_CAPACITY},
+};
+
+/// Size of the data stack in 8-byte cells
+const STACK_CAPACITY: usize = 1 << 20;
+
+const PRELUDE: &str = "\
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+";
+
+/// Runtime helpers, following the declarations of the stack, memory and
+/// registers
+const RUNTIME: &str = "\
+static void *address(int64_t addr, int64_t len)
+{
+	if (addr < MEM_BASE || addr - MEM_BASE > (int64_t)sizeof mem - len) {
+		fprintf(stderr, \"MemoryError: access of %\" PRId64 \" bytes at address %\" PRId64
+			\" is out of bounds\\n\", len, addr);
+		exit(1);
+	}
+	return (char *)mem + (addr - MEM_BASE);
+}
+
+static void print(int64_t v)
+{
+	printf(\"%\" PRId64 \"\\n\", v);
+}
+";
+
+/// C name of an LCL function, identifiers may contain characters C doesn't
+/// allow, so those are spelled out as `_` and their hex code
+pub(super) fn mangle(name: &str) -> String {
+    let mut res = String::from("lcl_");
+    for c in name.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => res.push(c),
+            '_' => res.push_str("__"),
+            _ => write!(res, "_{:x}_", c as u32).unwrap(),
+        }
+    }
+    res
+}
+
+fn literal(v: i64) -> String {
+    match v {
+        // `-9223372036854775808` is the negation of a literal out of range
+        i64::MIN => "INT64_MIN".to_string(),
+        _ => format!("INT64_C({})", v),
+    }
+}
+
+fn c_type(width: Option<Width>) -> &'static str {
+    match width {
+        None => "int64_t",
+        Some(Width::U8) => "uint8_t",
+        Some(Width::I8) => "int8_t",
+        Some(Width::U16) => "uint16_t",
+        Some(Width::I16) => "int16_t",
+        Some(Width::U32) => "uint32_t",
+        Some(Width::I32) => "int32_t",
+    }
+}
+
+/// Statements of `op` inside the function `current`, `None` for main
+fn op_to_c(op: &Op, current: Option<&Function>) -> String {
+    match op {
+        Op::PushConst(v) => format!("\t*sp++ = {};\n", literal(*v)),
+        Op::PushMem => "\t*sp++ = MEM_BASE;\n".to_string(),
+        Op::PushLocal(i) => format!("\t*sp++ = fp[-{}];\n", i + 1),
+        Op::Pick(n) => format!("\tsp[0] = sp[-{}];\n\tsp++;\n", n + 1),
+        Op::PushReg(i) => format!("\t*sp++ = regs[{}];\n", i - 1),
+        Op::PopReg(i) => format!("\tregs[{}] = *--sp;\n", i - 1),
+        Op::BinOp(op) => {
+            let expr = match op {
+                // Signed overflow is undefined in C, so wrap as unsigned
+                BinOp::Add => "(int64_t)((uint64_t)sp[-2] + (uint64_t)sp[-1])",
+                BinOp::Sub => "(int64_t)((uint64_t)sp[-2] - (uint64_t)sp[-1])",
+                BinOp::Less => "sp[-2] < sp[-1]",
+                BinOp::Greater => "sp[-2] > sp[-1]",
+                BinOp::Equal => "sp[-2] == sp[-1]",
+                BinOp::NotEqual => "sp[-2] != sp[-1]",
+            };
+            format!("\tsp[-2] = {};\n\tsp--;\n", expr)
+        }
+        Op::Stack(StackOp::Dup) => "\tsp[0] = sp[-1];\n\tsp++;\n".to_string(),
+        Op::Stack(StackOp::Drop) => "\tsp--;\n".to_string(),
+        Op::Stack(StackOp::Swap) => {
+            "\tt = sp[-1];\n\tsp[-1] = sp[-2];\n\tsp[-2] = t;\n".to_string()
+        }
+        Op::Stack(StackOp::Over) => "\tsp[0] = sp[-2];\n\tsp++;\n".to_string(),
+        Op::Stack(StackOp::Rot) => {
+            "\tt = sp[-3];\n\tsp[-3] = sp[-2];\n\tsp[-2] = sp[-1];\n\tsp[-1] = t;\n".to_string()
+        }
+        // Addresses are arbitrary, `memcpy` doesn't care about alignment
+        Op::Load(width) => format!(
+            "\t{{\n\t\t{0} v;\n\t\tmemcpy(&v, address(sp[-1], sizeof v), sizeof v);\n\t\tsp[-1] = v;\n\t}}\n",
+            c_type(*width)
+        ),
+        Op::Store(width) => format!(
+            "\t{{\n\t\t{0} v = ({0})sp[-1];\n\t\tmemcpy(address(sp[-2], sizeof v), &v, sizeof v);\n\t\tsp -= 2;\n\t}}\n",
+            c_type(*width)
+        ),
+        Op::Print => "\tprint(*--sp);\n".to_string(),
+        Op::Call { name, .. } => format!("\t{}();\n", mangle(name)),
+        Op::CallExtern { .. } => unreachable!("extern calls are rejected before translating"),
+        Op::TailCall { name, args } => {
+            let mut c = String::new();
+            for i in 0..*args {
+                writeln!(c, "\tfp[-{}] = sp[-{}];", i + 1, i + 1).unwrap();
+            }
+            c.push_str("\tsp = fp;\n");
+            match current {
+                // Calling itself never grows the C stack
+                Some(f) if f.name == *name => c.push_str("\tgoto entry;\n"),
+                // C compilers turn calls right before `return` into jumps
+                // when optimizing
+                _ => writeln!(c, "\t{}();\n\treturn;", mangle(name)).unwrap(),
+            }
+            c
+        }
+        Op::Label(l) => format!("L{}:;\n", l),
+        Op::Jump(l) => format!("\tgoto L{};\n", l),
+        Op::Branch(l) => format!("\tif (*--sp == 0)\n\t\tgoto L{};\n", l),
+    }
+}
+
+fn function(function: &Function) -> String {
+    let mut c = format!("static void {}(void)\n{{\n", mangle(&function.name));
+    c.push_str("\tint64_t *fp = sp;\n\tint64_t t;\n\t(void)fp;\n\t(void)t;\n");
+    let recurses = function.body.iter().any(|op| match op {
+        Op::TailCall { name, .. } => *name == function.name,
+        _ => false,
+    });
+    if recurses {
+        c.push_str("entry:;\n");
+    }
+    for op in function.body.iter() {
+        c.push_str(&op_to_c(op, Some(function)));
+    }
+    if function.returning {
+        writeln!(
+            c,
+            "\tt = sp[-1];\n\tsp = fp - {};\n\t*sp++ = t;",
+            function.params
+        )
+        .unwrap();
+    } else {
+        writeln!(c, "\tsp = fp - {};", function.params).unwrap();
+    }
+    c.push_str("}\n");
+    c
+}
+
+/// Translates `module` into a C program
+pub fn translate(module: &Module) -> String {
+    let mut c = String::from(PRELUDE);
+    writeln!(c).unwrap();
+    writeln!(c, "#define MEM_BASE INT64_C({})", MEM_BASE).unwrap();
+    writeln!(c).unwrap();
+    writeln!(c, "static int64_t stack[{}];", STACK_CAPACITY).unwrap();
+    writeln!(c, "static int64_t *sp = stack;").unwrap();
+    writeln!(c, "static int64_t mem[{}];", MEM_CAPACITY).unwrap();
+    writeln!(c, "static int64_t regs[{}];", REGISTERS).unwrap();
+    writeln!(c).unwrap();
+    c.push_str(RUNTIME);
+    writeln!(c).unwrap();
+
+    for f in module.functions.iter() {
+        writeln!(c, "static void {}(void);", mangle(&f.name)).unwrap();
+    }
+    for f in module.functions.iter() {
+        writeln!(c).unwrap();
+        c.push_str(&function(f));
+    }
+
+    c.push_str("\nint main(void)\n{\n\tint64_t t;\n\t(void)t;\n");
+    for op in module.main.iter() {
+        c.push_str(&op_to_c(op, None));
+    }
+    c.push_str("\treturn 0;\n}\n");
+    c
+}
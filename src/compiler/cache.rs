@@ -0,0 +1,272 @@
+//! Code generation keeping the top of the stack in registers.
+//!
+//! Up to two stack items live in registers instead of memory. They are
+//! flushed to the stack before calls, labels and jumps, so the stack is the
+//! same on every path reaching a label.
+
+use crate::{
+    ir::{BinOp, Op, StackOp},
+    lexer::tokens::Width,
+};
+
+use super::x86_64::{call_extern, register, tail_call};
+
+const CACHE: [&str; 2] = ["r8", "r9"];
+
+/// Name of the low `bytes` of a cache register
+fn sized(reg: &str, bytes: usize) -> String {
+    match bytes {
+        1 => format!("{}b", reg),
+        2 => format!("{}w", reg),
+        4 => format!("{}d", reg),
+        _ => reg.to_string(),
+    }
+}
+
+#[derive(Default)]
+pub struct TopOfStack {
+    /// Registers holding the top of the stack, deepest first
+    cached: Vec<&'static str>,
+    asm: String,
+    /// Position independent code, addresses are computed relative to `rip`
+    pic: bool,
+}
+
+impl TopOfStack {
+    pub fn new(pic: bool) -> Self {
+        Self {
+            pic,
+            ..Default::default()
+        }
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.asm.push('\t');
+        self.asm.push_str(instruction);
+        self.asm.push('\n');
+    }
+
+    fn top(&self) -> &'static str {
+        self.cached[self.cached.len() - 1]
+    }
+
+    /// Register for a new item on top of the stack, the deepest cached item
+    /// is spilled if there is none free
+    fn alloc(&mut self) -> &'static str {
+        if self.cached.len() == CACHE.len() {
+            let spilled = self.cached.remove(0);
+            self.emit(&format!("push {}", spilled));
+        }
+        let reg = CACHE
+            .into_iter()
+            .find(|r| !self.cached.contains(r))
+            .unwrap();
+        self.cached.push(reg);
+        reg
+    }
+
+    /// Loads items from the stack until the top `n` are in registers
+    fn ensure(&mut self, n: usize) {
+        while self.cached.len() < n {
+            let reg = CACHE
+                .into_iter()
+                .find(|r| !self.cached.contains(r))
+                .unwrap();
+            self.emit(&format!("pop {}", reg));
+            self.cached.insert(0, reg);
+        }
+    }
+
+    /// Operand holding the item `depth` places below the top of the stack
+    fn item(&self, depth: usize) -> String {
+        match self.cached.len().checked_sub(depth + 1) {
+            Some(idx) => self.cached[idx].to_string(),
+            None => match depth - self.cached.len() {
+                0 => "[rsp]".to_string(),
+                offset => format!("[rsp + {}]", offset * 8),
+            },
+        }
+    }
+
+    /// Removes the top item, returning its register
+    fn release(&mut self) -> &'static str {
+        self.cached.pop().unwrap()
+    }
+
+    /// Pushes every cached item onto the stack
+    fn flush(&mut self) {
+        for reg in std::mem::take(&mut self.cached) {
+            self.emit(&format!("push {}", reg));
+        }
+    }
+
+    fn push(&mut self, src: &str) {
+        let reg = self.alloc();
+        if reg != src {
+            self.emit(&format!("mov {}, {}", reg, src));
+        }
+    }
+
+    pub fn op(&mut self, op: &Op) {
+        self.asm.push_str(&format!("\t; {}\n", op));
+        match op {
+            Op::PushConst(v) => self.push(&v.to_string()),
+            Op::PushMem if self.pic => {
+                let reg = self.alloc();
+                self.emit(&format!("lea {}, [mem]", reg));
+            }
+            Op::PushMem => self.push("mem"),
+            // Arguments sit above the return address and the saved `rbp`
+            Op::PushLocal(i) => self.push(&format!("[rbp + {}]", 16 + i * 8)),
+            Op::Pick(n) => {
+                // Allocating may spill an item, so the picked one is looked
+                // up below the new top
+                let reg = self.alloc();
+                let src = self.item(n + 1);
+                if reg != src {
+                    self.emit(&format!("mov {}, {}", reg, src));
+                }
+            }
+            Op::PushReg(i) => self.push(register(*i)),
+            Op::PopReg(i) => {
+                self.ensure(1);
+                let top = self.release();
+                self.emit(&format!("mov {}, {}", register(*i), top));
+            }
+            Op::BinOp(op) => {
+                self.ensure(2);
+                let top = self.release();
+                let below = self.top();
+                let set = match op {
+                    BinOp::Add => {
+                        self.emit(&format!("add {}, {}", below, top));
+                        return;
+                    }
+                    BinOp::Sub => {
+                        self.emit(&format!("sub {}, {}", below, top));
+                        return;
+                    }
+                    BinOp::Less => "setl",
+                    BinOp::Greater => "setg",
+                    BinOp::Equal => "sete",
+                    BinOp::NotEqual => "setne",
+                };
+                self.emit(&format!("cmp {}, {}", below, top));
+                self.emit(&format!("{} {}", set, sized(below, 1)));
+                self.emit(&format!("movzx {}, {}", below, sized(below, 1)));
+            }
+            Op::Stack(StackOp::Dup) => {
+                let top = self.item(0);
+                self.push(&top);
+            }
+            Op::Stack(StackOp::Drop) => match self.cached.pop() {
+                Some(_) => {}
+                None => self.emit("add rsp, 8"),
+            },
+            Op::Stack(StackOp::Swap) => {
+                self.ensure(2);
+                self.cached.swap(0, 1);
+            }
+            Op::Stack(StackOp::Over) => {
+                let below = self.item(1);
+                self.push(&below);
+            }
+            Op::Stack(StackOp::Rot) => {
+                // a b c -> b c a, `a` is the only item not cached
+                self.ensure(2);
+                let (b, c) = (self.cached[0], self.cached[1]);
+                self.emit("pop r10");
+                self.emit(&format!("push {}", b));
+                self.emit(&format!("mov {}, r10", b));
+                self.cached = vec![c, b];
+            }
+            Op::Load(width) => {
+                self.ensure(1);
+                let top = self.top();
+                let load = match width {
+                    None => format!("mov {0}, [{0}]", top),
+                    Some(Width::U8) => format!("movzx {0}, BYTE [{0}]", top),
+                    Some(Width::I8) => format!("movsx {0}, BYTE [{0}]", top),
+                    Some(Width::U16) => format!("movzx {0}, WORD [{0}]", top),
+                    Some(Width::I16) => format!("movsx {0}, WORD [{0}]", top),
+                    Some(Width::U32) => format!("mov {}, DWORD [{}]", sized(top, 4), top),
+                    Some(Width::I32) => format!("movsxd {0}, DWORD [{0}]", top),
+                };
+                self.emit(&load);
+            }
+            Op::Store(width) => {
+                self.ensure(2);
+                let value = self.release();
+                let addr = self.release();
+                let bytes = width.map_or(8, |w| w.bytes());
+                self.emit(&format!("mov [{}], {}", addr, sized(value, bytes)));
+            }
+            Op::Print => {
+                self.ensure(1);
+                let top = self.release();
+                self.emit(&format!("mov rdi, {}", top));
+                self.flush();
+                self.emit("call print");
+            }
+            Op::Call {
+                name,
+                args,
+                returning,
+            } => {
+                self.flush();
+                self.emit(&format!("call {}", name));
+                if *args > 0 {
+                    self.emit(&format!("add rsp, {}", args * 8));
+                }
+                if *returning {
+                    self.push("rax");
+                }
+            }
+            Op::CallExtern {
+                name,
+                args,
+                returning,
+            } => {
+                self.flush();
+                self.asm.push_str(&call_extern(name, *args, self.pic));
+                if *returning {
+                    self.push("rax");
+                }
+            }
+            Op::TailCall { name, args } => {
+                self.flush();
+                self.asm.push_str(&tail_call(name, *args));
+            }
+            Op::Label(l) => {
+                self.flush();
+                self.asm.push_str(&format!(".L{}:\n", l));
+            }
+            Op::Jump(l) => {
+                self.flush();
+                self.emit(&format!("jmp .L{}", l));
+            }
+            Op::Branch(l) => {
+                self.ensure(1);
+                let top = self.release();
+                self.emit(&format!("test {0}, {0}", top));
+                // Pushes leave the flags alone
+                self.flush();
+                self.emit(&format!("jz .L{}", l));
+            }
+        }
+    }
+
+    /// Moves the result of a function into `rax`, returns the assembly
+    pub fn ret(mut self, returning: bool) -> String {
+        if returning {
+            self.ensure(1);
+            let top = self.top();
+            self.emit(&format!("mov rax, {}", top));
+        }
+        self.asm
+    }
+
+    pub fn into_asm(self) -> String {
+        self.asm
+    }
+}
@@ -0,0 +1,108 @@
+//! Rewrites the generated NASM assembly into GNU as Intel syntax.
+//!
+//! Code generation and the peephole optimizer only know NASM, so the
+//! finished assembly is translated line by line: comments start with `#`,
+//! since `;` separates statements in GNU as, memory operands spell out
+//! `PTR`, the `mem` symbol used as a value needs `OFFSET`, and the `resq`
+//! reservations become labelled `.skip`s. Directives get a leading `.`.
+//!
+//! After `default rel`, addresses based on a symbol are made relative to
+//! `rip` explicitly, and `wrt ..plt` calls go through `@PLT`.
+
+/// Memory operand sizes NASM writes before a `[`
+const SIZES: [&str; 4] = ["BYTE", "WORD", "DWORD", "QWORD"];
+
+/// General purpose registers that can be the base of an address
+const BASES: [&str; 16] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+/// Address between brackets, relative to `rip` if `rip` is set and its base
+/// is a symbol
+fn address(address: &str, rip: bool) -> String {
+    let base = address
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next();
+    match base {
+        Some(base) if rip && !base.is_empty() && !BASES.contains(&base) => {
+            format!("[rip + {}", address)
+        }
+        _ => format!("[{}", address),
+    }
+}
+
+/// Operand of an instruction in GNU as syntax
+fn operand(operand: &str, rip: bool) -> String {
+    let operand = operand.trim();
+    if operand == "mem" {
+        return "OFFSET mem".to_string();
+    }
+    if let Some(symbol) = operand.strip_suffix(" wrt ..plt") {
+        return format!("{}@PLT", symbol);
+    }
+    match operand.split_once(" [") {
+        Some((size, rest)) if SIZES.contains(&size) => {
+            format!("{} PTR {}", size, address(rest, rip))
+        }
+        _ => match operand.strip_prefix('[') {
+            Some(rest) => address(rest, rip),
+            None => operand.to_string(),
+        },
+    }
+}
+
+fn line(line: &str, rip: bool) -> String {
+    let code = line.trim_start();
+    let indent = &line[..line.len() - code.len()];
+    if let Some(comment) = code.strip_prefix(';') {
+        return format!("{}#{}", indent, comment);
+    }
+    if let Some(symbol) = code.strip_prefix("global ") {
+        return format!("\t.global {}", symbol);
+    }
+    if let Some(symbol) = code.strip_prefix("extern ") {
+        return format!("\t.extern {}", symbol);
+    }
+    if code.starts_with("section .note.GNU-stack") {
+        return "\t.section .note.GNU-stack,\"\",@progbits".to_string();
+    }
+    if let Some(section) = code.strip_prefix("section ") {
+        return format!("\t{}", section);
+    }
+    if code.is_empty() || code.ends_with(':') {
+        return line.to_string();
+    }
+
+    let mnemonic = code.split(char::is_whitespace).next().unwrap();
+    let operands = code[mnemonic.len()..].trim_start();
+    if let Some(("resq", cells)) = operands.split_once(' ') {
+        return format!(
+            "{}:\n\t.skip {}",
+            mnemonic,
+            cells.parse::<usize>().unwrap() * 8
+        );
+    }
+    if operands.is_empty() {
+        return line.to_string();
+    }
+    // Keeps the padding NASM lines use to align operands
+    let gap = &code[mnemonic.len()..code.len() - operands.len()];
+    let operands: Vec<String> = operands.split(',').map(|o| operand(o, rip)).collect();
+    format!("{}{}{}{}", indent, mnemonic, gap, operands.join(", "))
+}
+
+/// `asm` in GNU as Intel syntax
+pub fn translate(asm: &str) -> String {
+    let mut res = String::from("\t.intel_syntax noprefix\n");
+    let mut rip = false;
+    for l in asm.lines() {
+        if l.trim() == "default rel" {
+            rip = true;
+            continue;
+        }
+        res.push_str(&line(l, rip));
+        res.push('\n');
+    }
+    res
+}
@@ -0,0 +1,51 @@
+//! C header declaring the functions an object file exports.
+//!
+//! Exported functions are named like in C source, see `c::mangle`, and take
+//! their parameters in declaration order, the first being the one on top of
+//! the stack in LCL.
+
+use std::fmt::Write;
+
+use super::c::mangle;
+use crate::ir::{Function, Module};
+
+/// Prototype of the C function calling `function`
+fn prototype(function: &Function) -> String {
+    let params = match function.params {
+        0 => "void".to_string(),
+        n => vec!["int64_t"; n].join(", "),
+    };
+    let result = if function.returning {
+        "int64_t"
+    } else {
+        "void"
+    };
+    format!("{} {}({});", result, mangle(&function.name), params)
+}
+
+/// Header declaring the exported functions of `module`, `name` is the stem
+/// of the header file and names its include guard
+pub fn translate(module: &Module, name: &str) -> String {
+    let guard: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+
+    let mut h = String::new();
+    writeln!(h, "/* Functions exported by {}, generated by lcl */", name).unwrap();
+    writeln!(h, "#ifndef LCL_{}_H", guard).unwrap();
+    writeln!(h, "#define LCL_{}_H", guard).unwrap();
+    writeln!(h).unwrap();
+    writeln!(h, "#include <stdint.h>").unwrap();
+    writeln!(h).unwrap();
+    for function in module.functions.iter().filter(|f| f.export) {
+        writeln!(h, "/* {} */", function.name).unwrap();
+        writeln!(h, "{}", prototype(function)).unwrap();
+    }
+    writeln!(h).unwrap();
+    writeln!(h, "#endif").unwrap();
+    h
+}
@@ -1,366 +1,375 @@
-mod capture;
+mod aarch64;
+mod c;
+mod cache;
+mod gas;
+mod header;
+mod peephole;
+mod wat;
+mod x86_64;
 
 use std::{
-    fs::File,
-    io::{BufWriter, Error, Result, Write},
+    env, fs,
+    io::{Error, Result, Write},
+    path::Path,
     process::Command,
-    str::from_utf8, collections::HashMap,
+    str::from_utf8,
 };
 
+use clap::ArgEnum;
+
+use self::{aarch64::Aarch64, x86_64::X86_64};
 use crate::{
-    lexer::tokens::{TargetType, Token, TokenType},
-    lib::utils::Location,
+    ir::{
+        self, fold::ConstantFold, inline::Inline, pass::PassManager, tail::TailCalls, Function,
+        Module, Op,
+    },
+    lexer::tokens::Token,
+    lib::{
+        constants::EXTERN_PARAMS,
+        errors::{LocatedError, LocatedWarning},
+    },
 };
 
-use self::capture::Capture;
+#[cfg(test)]
+pub use self::peephole::count;
 
-struct Compiler {
-    handler: BufWriter<File>,
-    markers: Vec<(usize, Location)>,
-    mem_capacity: i32,
+/// Platform code is generated for
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// x86_64 Linux assembly, built with nasm and ld
+    #[default]
+    #[clap(name = "x86_64")]
+    X86_64,
+    /// AArch64 Linux assembly, built with GNU as and ld
+    Aarch64,
+    /// Portable C source, built with the system C compiler
+    C,
+    /// WebAssembly text, importing its output functions from the host
+    Wat,
+}
 
-    functions: HashMap<String, (String, usize, bool, bool)>,
-    capture: Option<Capture>
+/// Assembler x86_64 code is written for
+#[derive(ArgEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Assembler {
+    #[default]
+    Nasm,
+    /// GNU as, with Intel syntax
+    Gas,
 }
 
-impl Compiler {
-    fn new(outfile: &str) -> Self {
-        let file =
-            File::create(format!("{}.{}", &outfile, "asm")).expect("failed to create asm file");
-        let handler = BufWriter::new(file);
-        Self {
-            handler,
-            mem_capacity: 262144,
-            markers: Vec::new(),
-            capture: None,
-            functions: HashMap::new()
+impl Assembler {
+    /// NASM if it's on `PATH`, otherwise GNU as if that is
+    pub fn detect() -> Self {
+        match (on_path("nasm"), on_path("as")) {
+            (false, true) => Self::Gas,
+            _ => Self::Nasm,
         }
     }
+}
 
-    fn translate_tokens(&mut self, program: &[Token]) -> Result<()> {
-        let mut start_body = String::new();
-        for (idx, token) in program.iter().enumerate() {
-            let asm = self.token_to_asm(token, idx, program)?;
-            if self.capture.is_some() {
-                self.capture.as_mut().unwrap().push_asm(&asm)
-            } else {
-                start_body.push_str(&asm);
-            }
-        }
+/// Whether an executable named `tool` is in one of the directories of
+/// `PATH`
+fn on_path(tool: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(tool).is_file()))
+        .unwrap_or(false)
+}
 
-        if !self.markers.is_empty() {
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "CompilationError: not enclosed block at {}",
-                    self.markers.last().unwrap().1
-                ),
-            ));
-        }
+/// Kind of file a compilation produces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Program starting at the top-level code
+    #[default]
+    Exe,
+    /// ELF object exporting the `export fn` functions to C, without the
+    /// top-level code, and a C header declaring them
+    Obj,
+    /// Static library holding that object, and the header
+    Staticlib,
+}
 
-        if let Some(c) = &self.capture {
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "CompilationError: not enclosed function at {}",
-                    c.loc
-                ),
-            ));
-        }
-        
-        self.headers()?;
-        writeln!(self.handler, "{}", &start_body)?;
-        self.footers()?;
-        self.flush()?;
+/// Settings of a compilation
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// `1` inlines small functions, folds constants and runs the peephole
+    /// optimizer over the assembly, `2` also keeps the top of the stack in
+    /// registers
+    pub opt_level: u8,
+    pub target: Platform,
+    pub assembler: Assembler,
+    /// Link against libc with `cc`, the program then starts at `main` and
+    /// ends by calling `exit`
+    pub libc: bool,
+    /// Libraries linked into the executable, passed as `-l<LIB>`
+    pub link_libs: Vec<String>,
+    /// Object files linked into the executable
+    pub link_objs: Vec<String>,
+    /// Kind of file to build
+    pub output: Output,
+}
+
+/// Assembly for an instruction set and operating system. The program is
+/// laid out as the headers, the functions, the entry point running the
+/// top-level code and the footers
+pub trait Target {
+    /// Start of a comment running to the end of the line
+    fn comment(&self) -> &'static str;
+
+    fn function(&self, function: &Function) -> String;
+
+    /// Top-level code, run from the entry point
+    fn body(&self, ops: &[Op]) -> String;
+
+    /// Declarations of the extern functions the program calls, before the
+    /// headers
+    fn externs(&self, _names: &[String], _handler: &mut dyn Write) -> Result<()> {
         Ok(())
     }
 
-    fn token_to_asm(&mut self, token: &Token, idx: usize, program: &[Token]) -> Result<String> {
-        match &token.ttype {
-            TokenType::Plus => {
-                Ok("\t; Plus\n\tpop  rax\n\tpop  rbx\n\tadd  rax, rbx\n\tpush rax\n".to_string())
-            }
-            TokenType::Minus => {
-                Ok("\t; Minus\n\tpop  rax\n\tpop  rbx\n\tsub  rbx, rax\n\tpush rbx\n".to_string())
-            }
-            TokenType::Dot => {
-                Ok("\t; Dot\n\tpop  rdi\n\tcall print\n".to_string())
-            }
-            TokenType::Less => {
-                Ok("\t; Less\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rbx\n\tpop rax\n\tcmp rax, rbx\n\tcmovl rcx, rdx\n\tpush rcx\n".to_string())
-            }
-            TokenType::Greater => {
-                Ok("\t; Greater\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rbx\n\tpop rax\n\tcmp rax, rbx\n\tcmovg rcx, rdx\n\tpush rcx\n".to_string())
-            }
-            TokenType::Equal => {
-                Ok("\t; Equal\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rax\n\tpop rbx\n\tcmp rax, rbx\n\tcmove rcx, rdx\n\tpush rcx\n".to_string())
-            }
-            TokenType::NotEqual => {
-                Ok("\t; NotEqual\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rax\n\tpop rbx\n\tcmp rax, rbx\n\tcmovne rcx, rdx\n\tpush rcx\n".to_string())
-            }
-            TokenType::If => {
-                self.markers.push((idx, token.loc.clone()));
-                Ok(format!("\t; If\n\tpop rax\n\ttest rax, rax\n\tjz e{}\n", idx))
-            }
-            TokenType::Else => {
-                if let Some((m, _)) = self.markers.pop() {
-                    let r = format!("\tjmp e{}\ne{}:\n", idx, &m);
-                    self.markers.push((idx, token.loc.clone()));
-                    Ok(r)
-                } else {
-                    return Err(Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("CompilationError: unexpected `else` at {}", token.loc),
-                    ));
-                }
-            }
-            TokenType::While => {
-                self.markers.push((idx, token.loc.clone()));
-                Ok(format!("\t; While:start of loop condition\nl{}:\n", idx))
-            }
-            TokenType::Do => {
-                if let Some((m, _)) = self.markers.last() {
-                    Ok(format!("\t; Do:end of loop condition\n\tpop rax\n\ttest rax, rax\n\tjz e{}\n", m))
-                } else if self.capture.is_some() && self.capture.as_ref().unwrap().initializing {
-                    self.capture.as_mut().unwrap().initializing = false;
-                    Ok("".to_string())
-                } else {
-                    return Err(Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("CompilationError: unexpected `do` at {}", token.loc),
-                    ));
-                }
-            }
-            TokenType::End => {
-                if let Some((m, _)) = self.markers.pop() {
-                    match program[m].ttype {
-                        TokenType::While => {
-                            Ok(format!("\tjmp l{0}\ne{0}:\n", &m))
-                        }
-                        _ => Ok(format!("e{}:\n", &m))
-                    }
-                } else if self.capture.is_some() {
-                    self.functions.insert(
-                        self.capture.as_ref().unwrap().get_name().to_string(), 
-                        (
-                                self.capture.as_mut().unwrap().get_source().to_string(), 
-                                self.capture.as_mut().unwrap().last_offset(),
-                                self.capture.as_mut().unwrap().returning,
-                                self.capture.as_mut().unwrap().inline
-                            )
-                    );
-                    self.capture = None;
-                    Ok("".to_string())
-                } else {
-                    return Err(Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("CompilationError: unexpected end of block at {}", token.loc),
-                    ));
-                }
-            }
-            TokenType::Identifier(ident) => match ident.as_str() {
-                "dup" => {
-                    Ok("\t; DUP\n\tpop rax\n\tpush rax\n\tpush rax\n".to_string())
-                }
-                "drop" => {
-                    Ok("\t; DROP\n\tpop rax\n\txor rax, rax\n".to_string())
-                }
-                "swap" => {
-                    Ok("\t; SWAP\n\tpop rax\n\tpop rbx\n\tpush rax\n\tpush rbx\n".to_string())
-                }
-                "over" => {
-                    Ok("\t; OVER\n\tpop rax\n\tpop rbx\n\tpush rbx\n\tpush rax\n\tpush rbx\n".to_string())
-                }
-                "rot" => {
-                    Ok("\t; ROT\n\tpop rax\n\tpop rbx\n\tpop rcx\n\tpush rbx\n\tpush rax\n\tpush rcx\n".to_string())
-                }
-                name if self.capture.is_some() && !self.capture.as_ref().unwrap().has_name() => {
-                    self.capture.as_mut().unwrap().set_name(name);
-                    Ok("".to_string())
-                }
-                name if self.capture.is_some() && self.capture.as_ref().unwrap().initializing && self.capture.as_ref().unwrap().has_name() => {
-                    self.capture.as_mut().unwrap().add_local_var(name);
-                    Ok("".to_string())
-                }
-                name
-                    if 
-                        self.capture.is_some() &&
-                        !self.capture.as_ref().unwrap().initializing &&
-                        self.capture.as_ref().unwrap().has_local_var(name) => 
-                {
-                    let var = self.capture.as_ref().unwrap().get_local_var(name);
-                    Ok(format!("\t; Push {}\n\tmov rax, {}\n\tpush rax\n", name, var))
-                }
-                other => {
-                    if let Some((source, size, returning, inline)) = self.functions.get(other) {
-                        if !inline {
-                            Ok(format!(
-                                "\t; Call {0}\n\tcall {0}\n\tadd rsp, {1}\n{2}", 
-                                other, 
-                                size, 
-                                if *returning { "\tpush rax\n" } else { "" }
-                            ))
-                        } else {
-                            Ok(format!("\t; Inline call {}\n{}", &other, source))
-                        }
-                    } else {
-                        return Err(Error::new(
-                            std::io::ErrorKind::Other,
-                            format!("CompilationError: {} is not defined at {}", ident, token.loc),
-                        ))
-                    }
-                }
-            }
-            TokenType::Mem => {
-                Ok("\t; MEM\n\tpush mem".to_string())
-            }
-            TokenType::Push(target) => {
-                match target {
-                    TargetType::Integer(n) => Ok(format!("\t; Push {0}\n\tmov  rax, {0}\n\tpush rax\n", n)),
-                    TargetType::Memory => Ok("\t; Load\n\tpop rax\n\txor rbx, rbx\n\tmov rbx, [rax]\n\tpush rbx\n".to_string()),
-                    TargetType::Regsiter(i) => {
-                        let reg = self.get_register(*i)?;
-                        Ok(format!("\t; Push {0}\n\tpush {0}\n", reg))
-                    },
-                }
-            }
-            TokenType::Pop(target) => {
-                match target {
-                    TargetType::Memory => Ok("\t; Store\n\tpop rax\n\tpop rbx\n\tmov [rbx], rax\n".to_string()),
-                    TargetType::Regsiter(i) => {
-                        let reg = self.get_register(*i)?;
-                        Ok(format!("\t; Pop {0}\n\tpop {0}\n", reg))
-                    },
-                    TargetType::Integer(_) => Err(Error::new(
-                        std::io::ErrorKind::Other, 
-                        format!("CompilationError: cannot pop from immediate integer value at {}", token.loc)
-                    )),
-                }
-            }
-            TokenType::Function => {
-                if self.capture.is_none() {
-                    self.capture = Some(Capture::new(token.loc.clone(), false));
-                }
-                Ok("".to_string())
-            }
-            TokenType::Inline => {
-                if self.capture.is_none() {
-                    self.capture = Some(Capture::new(token.loc.clone(), true));
-                }
-                Ok("".to_string())
-            }
-            TokenType::Multiply => unimplemented!(),
-            TokenType::Divide => unimplemented!(),
-            TokenType::Mod => unimplemented!(),
-        }
+    /// Directives and the runtime routines generated code calls
+    fn headers(&self, handler: &mut dyn Write) -> Result<()>;
+
+    /// Entry point of the program, up to the top-level code
+    fn entry(&self, handler: &mut dyn Write) -> Result<()>;
+
+    /// Functions C code calls the exported `functions` through, replacing
+    /// the entry point and the top-level code in object files
+    fn exports(&self, _functions: &[&Function], _handler: &mut dyn Write) -> Result<()> {
+        Ok(())
     }
 
-    fn get_register(&self, idx: usize) -> Result<String> {
-        match &idx {
-            1 => Ok("rax".to_string()),
-            2 => Ok("rbx".to_string()),
-            3 => Ok("rcx".to_string()),
-            4 => Ok("rdx".to_string()),
-            _ => Err(Error::new(
-                std::io::ErrorKind::Other, 
-                format!("CompilationError: invalid register index {}", idx)
-            ))
-        }
+    /// Exit of the program after the top-level code, and its data
+    fn footers(&self, handler: &mut dyn Write) -> Result<()>;
+
+    /// Rewrites the finished assembly, into the syntax of another
+    /// assembler for example
+    fn finish(&self, asm: String) -> String {
+        asm
+    }
+}
+
+/// Target generating the assembly of `options.target`
+fn target(options: &Options) -> Box<dyn Target> {
+    match options.target {
+        Platform::X86_64 => Box::new(X86_64::new(options)),
+        Platform::Aarch64 => Box::new(Aarch64::new(options)),
+        Platform::C | Platform::Wat => unreachable!("{:?} isn't assembly", options.target),
     }
+}
 
-    fn headers(&mut self) -> Result<()> {
-        writeln!(self.handler, "global _start")?;
-        writeln!(self.handler, "section .text")?;
-
-        // Print function
-        writeln!(self.handler, "print:")?;
-        writeln!(self.handler, "\tsub     rsp, 40")?;
-        writeln!(self.handler, "\tmov     rsi, rdi")?;
-        writeln!(self.handler, "\tmov     r10, -3689348814741910323")?;
-        writeln!(self.handler, "\tmov     BYTE [rsp+19], 10")?;
-        writeln!(self.handler, "\tlea     rcx, [rsp+18]")?;
-        writeln!(self.handler, "\tlea     r8, [rsp+20]")?;
-        writeln!(self.handler, ".L2:")?;
-        writeln!(self.handler, "\tmov     rax, rsi")?;
-        writeln!(self.handler, "\tmov     r9, r8")?;
-        writeln!(self.handler, "\tmul     r10")?;
-        writeln!(self.handler, "\tmov     rax, rsi")?;
-        writeln!(self.handler, "\tsub     r9, rcx")?;
-        writeln!(self.handler, "\tshr     rdx, 3")?;
-        writeln!(self.handler, "\tlea     rdi, [rdx+rdx*4]")?;
-        writeln!(self.handler, "\tadd     rdi, rdi")?;
-        writeln!(self.handler, "\tsub     rax, rdi")?;
-        writeln!(self.handler, "\tadd     eax, 48")?;
-        writeln!(self.handler, "\tmov     BYTE [rcx], al")?;
-        writeln!(self.handler, "\tmov     rax, rsi")?;
-        writeln!(self.handler, "\tmov     rsi, rdx")?;
-        writeln!(self.handler, "\tmov     rdx, rcx")?;
-        writeln!(self.handler, "\tsub     rcx, 1")?;
-        writeln!(self.handler, "\tcmp     rax, 9")?;
-        writeln!(self.handler, "\tja      .L2")?;
-        writeln!(self.handler, "\tsub     rdx, r8")?;
-        writeln!(self.handler, "\tmov     edi, 1")?;
-        writeln!(self.handler, "\txor     eax, eax")?;
-        writeln!(self.handler, "\tlea     rsi, [rsp+20+rdx]")?;
-        writeln!(self.handler, "\tmov     rdx, r9")?;
-        writeln!(self.handler, "\tmov     rax, 1")?;
-        writeln!(self.handler, "\tsyscall")?;
-        writeln!(self.handler, "\tadd     rsp, 40")?;
-        writeln!(self.handler, "\tret")?;
-
-        for func in self.functions.iter() {
-            if !func.1.3 {
-                writeln!(self.handler, "{}", func.1.0)?;
-            }
+fn located(e: LocatedError) -> Error {
+    Error::other(e.to_string())
+}
+
+/// Passes run over the IR with the given options
+fn passes(options: &Options) -> PassManager {
+    let mut passes = PassManager::new();
+    if options.opt_level >= 1 {
+        passes.add(Inline);
+        passes.add(ConstantFold);
+    }
+    passes.add(TailCalls);
+    passes
+}
+
+/// Lowers `program` into IR and runs the passes enabled by `options`,
+/// returns the module with the warnings found along the way
+pub fn build(program: &[Token], options: &Options) -> Result<(Module, Vec<LocatedWarning>)> {
+    if options.output != Output::Exe && options.target != Platform::X86_64 {
+        return Err(Error::other(
+            "object files and static libraries can only be built for x86_64",
+        ));
+    }
+    let (mut module, warnings) = ir::lower(program.to_vec()).map_err(located)?;
+    if options.output != Output::Exe {
+        let mut exported = module.functions.iter().filter(|f| f.export);
+        if let Some(f) = exported.find(|f| f.params > EXTERN_PARAMS) {
+            return Err(Error::other(format!(
+                "exported function {} takes {} parameters, at most {} can be passed from C",
+                f.name, f.params, EXTERN_PARAMS
+            )));
         }
+    }
+    passes(options).run(&mut module);
+    Ok((module, warnings))
+}
 
-        writeln!(self.handler, "_start:")?;
+/// Translates `program` into the assembly of its functions and top-level
+/// code, without the runtime around them
+pub fn translate(program: &[Token], options: &Options) -> Result<String> {
+    let (module, _) = build(program, options)?;
+    let target = target(options);
 
-        Ok(())
+    let mut asm = String::new();
+    for function in module.functions.iter() {
+        asm.push_str(&target.function(function));
     }
+    asm.push_str(&target.body(&module.main));
+    Ok(target.finish(asm))
+}
 
-    fn footers(&mut self) -> Result<()> {
-        writeln!(self.handler, "\tmov rax, 60")?;
-        writeln!(self.handler, "\tmov rdi, 0")?;
-        writeln!(self.handler, "\tsyscall")?;
-        writeln!(self.handler, "\tret")?;
+/// Translates `program` into a complete assembly file, or C source for
+/// `Platform::C` and WebAssembly text for `Platform::Wat`, returns it with
+/// the warnings found along the way
+pub fn assemble(program: &[Token], options: &Options) -> Result<(String, Vec<LocatedWarning>)> {
+    let (module, warnings) = build(program, options)?;
+    Ok((assemble_module(&module, options)?, warnings))
+}
 
-        writeln!(self.handler, "section .bss")?;
-        writeln!(self.handler, "\tmem resq {}", self.mem_capacity)?;
+/// Translates the built `module` into a complete assembly file, or C source
+/// for `Platform::C` and WebAssembly text for `Platform::Wat`
+fn assemble_module(module: &Module, options: &Options) -> Result<String> {
+    if let (Platform::C | Platform::Wat, Some(name)) = (options.target, module.externs.first()) {
+        return Err(Error::other(format!(
+            "extern function {} can't be called from {:?} code, only from assembly targets",
+            name, options.target
+        )));
+    }
+    match options.target {
+        Platform::C => return Ok(c::translate(module)),
+        Platform::Wat => return Ok(wat::translate(module)),
+        Platform::X86_64 | Platform::Aarch64 => {}
+    }
+    let target = target(options);
 
-        Ok(())
+    let mut asm = Vec::new();
+    for (name, decision) in module.inlining.iter() {
+        writeln!(asm, "{} {}: {}", target.comment(), name, decision)?;
+    }
+    target.externs(&module.externs, &mut asm)?;
+    target.headers(&mut asm)?;
+    for function in module.functions.iter() {
+        writeln!(asm, "{}", target.function(function))?;
     }
+    if options.output == Output::Exe {
+        target.entry(&mut asm)?;
+        writeln!(asm, "{}", target.body(&module.main))?;
+    } else {
+        let exported: Vec<&Function> = module.functions.iter().filter(|f| f.export).collect();
+        target.exports(&exported, &mut asm)?;
+    }
+    target.footers(&mut asm)?;
+    Ok(target.finish(String::from_utf8(asm).unwrap()))
+}
 
-    fn flush(&mut self) -> Result<()> {
-        self.handler.flush()
+/// Builds an executable, or the object file or static library of
+/// `options.output` with a C header next to it, from `program`, returns the
+/// warnings found along the way
+pub fn compile(program: &[Token], out: &str, options: &Options) -> Result<Vec<LocatedWarning>> {
+    match options.target {
+        Platform::C => return compile_c(program, out, options),
+        Platform::Wat => return compile_wat(program, out, options),
+        Platform::X86_64 | Platform::Aarch64 => {}
     }
+
+    let (module, warnings) = build(program, options)?;
+    let asm = assemble_module(&module, options)?;
+    let prefix = cross_prefix(options.target)?;
+    let ext = match (options.target, options.assembler) {
+        (Platform::X86_64, Assembler::Nasm) => "asm",
+        _ => "s",
+    };
+    // Executables keep the intermediate files next to them, libraries
+    // replace their extension
+    let path = Path::new(out);
+    let with = |ext| path.with_extension(ext).to_str().unwrap().to_string();
+    let (source, object) = match options.output {
+        Output::Exe => (format!("{}.{}", out, ext), format!("{}.o", out)),
+        Output::Obj => (with(ext), out.to_string()),
+        Output::Staticlib => (with(ext), with("o")),
+    };
+    fs::write(&source, asm)?;
+
+    match ext {
+        "asm" => run("nasm", &["-felf64", "-o", object.as_str(), source.as_str()])?,
+        _ => run(
+            &format!("{}as", prefix),
+            &["-o", object.as_str(), source.as_str()],
+        )?,
+    }
+    match options.output {
+        Output::Exe => return link(&object, out, options, prefix).map(|()| warnings),
+        Output::Obj => {}
+        Output::Staticlib => run("ar", &["rcs", out, object.as_str()])?,
+    }
+
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    fs::write(with("h"), header::translate(&module, name))?;
+    Ok(warnings)
 }
 
-pub fn compile(program: &mut Vec<Token>, out: &str) -> Result<()> {
-    let mut compiler = Compiler::new(out);
-    compiler.translate_tokens(program)?;
+/// Prefix of the binutils that build code for `target`, empty for the
+/// host's own and `aarch64-linux-gnu-` for AArch64 code on other hosts
+fn cross_prefix(target: Platform) -> Result<&'static str> {
+    if target != Platform::Aarch64 || env::consts::ARCH == "aarch64" {
+        return Ok("");
+    }
+    let prefix = "aarch64-linux-gnu-";
+    if on_path(&format!("{}as", prefix)) && on_path(&format!("{}ld", prefix)) {
+        return Ok(prefix);
+    }
+    Err(Error::other(format!(
+        "building AArch64 code on {} needs {}as and {}ld on PATH, \
+        use --emit=asm to only generate the assembly",
+        env::consts::ARCH,
+        prefix,
+        prefix
+    )))
+}
 
-    let output = Command::new("nasm")
-        .args(["-felf64", format!("{}.{}", &out, "asm").as_str()])
-        .output()
-        .expect("failed to run nasm");
-    if !output.stderr.is_empty() {
-        return Err(Error::new(
-            std::io::ErrorKind::Other,
-            from_utf8(&output.stderr).unwrap().to_string(),
-        ));
+/// Links `object` with the objects and libraries of `options` into the
+/// executable `out`, through `cc` when linking against libc and `ld`
+/// otherwise, both named with the cross `prefix`
+fn link(object: &str, out: &str, options: &Options, prefix: &str) -> Result<()> {
+    let libs: Vec<String> = options
+        .link_libs
+        .iter()
+        .map(|lib| format!("-l{}", lib))
+        .collect();
+    let mut args = vec!["-o", out, object];
+    args.extend(options.link_objs.iter().map(String::as_str));
+    args.extend(libs.iter().map(String::as_str));
+    if options.libc {
+        // Generated code uses absolute addresses of `mem`
+        args.insert(0, "-no-pie");
+        match prefix {
+            "" => run("cc", &args),
+            _ => run(&format!("{}gcc", prefix), &args),
+        }
+    } else {
+        run(&format!("{}ld", prefix), &args)
     }
+}
 
-    let output = Command::new("ld")
-        .args(["-o", out, format!("{}.{}", &out, "o").as_str()])
+/// Runs `tool` with `args`, failing with its error output if it can't be
+/// started or doesn't succeed
+fn run(tool: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(tool)
+        .args(args)
         .output()
-        .expect("failed to run ld");
-    if !output.stderr.is_empty() {
-        return Err(Error::new(
-            std::io::ErrorKind::Other,
-            from_utf8(&output.stderr).unwrap().to_string(),
-        ));
+        .map_err(|e| Error::other(format!("failed to run {}: {}", tool, e)))?;
+    if !output.status.success() {
+        return Err(Error::other(from_utf8(&output.stderr).unwrap().to_string()));
     }
-
     Ok(())
 }
+
+/// Builds an executable from `program` through C source written next to
+/// `out`, with the system C compiler
+fn compile_c(program: &[Token], out: &str, options: &Options) -> Result<Vec<LocatedWarning>> {
+    let (src, warnings) = assemble(program, options)?;
+    let path = format!("{}.c", out);
+    fs::write(&path, src)?;
+
+    run("cc", &["-O2", "-o", out, path.as_str()])?;
+
+    Ok(warnings)
+}
+
+/// Writes the WebAssembly text of `program` next to `out`, turning it into
+/// a binary module is left to the host's tools
+fn compile_wat(program: &[Token], out: &str, options: &Options) -> Result<Vec<LocatedWarning>> {
+    let (src, warnings) = assemble(program, options)?;
+    fs::write(format!("{}.wat", out), src)?;
+    Ok(warnings)
+}
@@ -0,0 +1,95 @@
+//! Rewrites of short instruction sequences in the emitted assembly.
+//!
+//! Comments are kept and skipped over when matching, labels end a sequence
+//! since control may reach them from elsewhere.
+
+/// Operands of an instruction line, `None` for comments, labels and blank
+/// lines
+fn instruction(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with(';') || line.ends_with(':') {
+        return None;
+    }
+    let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operands = operands
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect();
+    Some((mnemonic, operands))
+}
+
+fn is_label(line: &str) -> bool {
+    line.trim().ends_with(':')
+}
+
+/// Index of the last instruction in `lines`, unless a label comes after it
+fn last_instruction(lines: &[String]) -> Option<usize> {
+    for (idx, line) in lines.iter().enumerate().rev() {
+        if is_label(line) {
+            return None;
+        }
+        if instruction(line).is_some() {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// Replacement of the instructions `prev` followed by `cur`, `Some(None)`
+/// if both can be removed
+fn combine(prev: &str, cur: &str) -> Option<Option<String>> {
+    let (prev_op, prev_args) = instruction(prev)?;
+    let (cur_op, cur_args) = instruction(cur)?;
+    match (prev_op, prev_args.as_slice(), cur_op, cur_args.as_slice()) {
+        // The value never leaves the register
+        ("push", [src], "pop", [dst]) if src == dst => Some(None),
+        // `mov` takes at most one memory operand
+        ("push", [src], "pop", [dst]) if !(src.contains('[') && dst.contains('[')) => {
+            Some(Some(format!("\tmov {}, {}", dst, src)))
+        }
+        ("push", [_], "add", ["rsp", "8"]) => Some(None),
+        // The first value is overwritten before it is read, stores may be
+        // narrower than the one they follow
+        ("mov", [dst, _], "mov", [next, src])
+            if dst == next && !dst.contains('[') && !src.contains(dst) =>
+        {
+            Some(Some(cur.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Number of instructions in `asm`
+#[cfg(test)]
+pub fn count(asm: &str) -> usize {
+    asm.lines().filter(|l| instruction(l).is_some()).count()
+}
+
+pub fn optimize(asm: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in asm.lines() {
+        if let Some((mnemonic, operands)) = instruction(line) {
+            if mnemonic == "mov" && operands.len() == 2 && operands[0] == operands[1] {
+                continue;
+            }
+        }
+
+        // A replacement may combine with the instruction before it as well
+        let mut cur = Some(line.to_string());
+        while let Some(line) = cur.take() {
+            match last_instruction(&lines).and_then(|idx| Some((idx, combine(&lines[idx], &line)?)))
+            {
+                Some((idx, replacement)) => {
+                    lines.remove(idx);
+                    cur = replacement;
+                }
+                None => lines.push(line),
+            }
+        }
+    }
+
+    let mut res = lines.join("\n");
+    res.push('\n');
+    res
+}
@@ -0,0 +1,385 @@
+//! WebAssembly text generated from IR.
+//!
+//! The data stack lives at the start of the linear memory with `$sp`
+//! pointing past its top, and the `mem` block follows it at the address
+//! `mem` pushes in the interpreter, so addresses are used as they are once
+//! checked. Every function becomes a wasm function without parameters
+//! working on the data stack, like in the C backend.
+//!
+//! Wasm only has structured control flow, so bodies with labels are split
+//! into blocks at every label and run inside a loop dispatching on `$pc`,
+//! jumps set it and restart the loop. Other tail calls use `return_call`
+//! from the tail call extension, part of WebAssembly 3.0.
+//!
+//! `.` calls the imported `env.print` and out of bounds accesses call
+//! `env.memory_error` before trapping, the module exports its memory and
+//! `main`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::c::mangle;
+use crate::{
+    interpreter::REGISTERS,
+    ir::{BinOp, Function, Label, Module, Op, StackOp},
+    lexer::tokens::Width,
+    lib::constants::{MEM_BASE, MEM_CAPACITY},
+};
+
+/// Size of a wasm page in bytes
+const PAGE: usize = 65536;
+
+/// Helpers moving items on the data stack and checking addresses, `{size}`
+/// is the size of the `mem` block in bytes
+const RUNTIME: &str = "  (func $push (param $v i64)
+    global.get $sp
+    local.get $v
+    i64.store
+    global.get $sp
+    i32.const 8
+    i32.add
+    global.set $sp
+  )
+  (func $pop (result i64)
+    global.get $sp
+    i32.const 8
+    i32.sub
+    global.set $sp
+    global.get $sp
+    i64.load
+  )
+  (func $pick (param $n i32)
+    global.get $sp
+    local.get $n
+    i32.const 8
+    i32.mul
+    i32.sub
+    i32.const 8
+    i32.sub
+    i64.load
+    call $push
+  )
+  (func $address (param $addr i64) (param $len i64) (result i32)
+    local.get $addr
+    i64.const {base}
+    i64.lt_s
+    local.get $addr
+    i64.const {base}
+    i64.sub
+    i64.const {size}
+    local.get $len
+    i64.sub
+    i64.gt_s
+    i32.or
+    if
+      local.get $addr
+      local.get $len
+      call $memory_error
+      unreachable
+    end
+    local.get $addr
+    i32.wrap_i64
+  )
+";
+
+/// Instructions of a function, indented by the blocks they are in
+struct Emitter {
+    wat: String,
+    depth: usize,
+}
+
+impl Emitter {
+    fn new() -> Self {
+        Self {
+            wat: String::new(),
+            depth: 2,
+        }
+    }
+
+    fn emit(&mut self, instr: &str) {
+        if instr == "end" {
+            self.depth -= 1;
+        }
+        writeln!(self.wat, "{}{}", "  ".repeat(self.depth), instr).unwrap();
+        if instr.starts_with("block") || instr.starts_with("loop") || instr == "if" {
+            self.depth += 1;
+        }
+    }
+
+    fn all(&mut self, instrs: &[&str]) {
+        instrs.iter().for_each(|i| self.emit(i));
+    }
+}
+
+fn load(width: Option<Width>) -> (&'static str, usize) {
+    match width {
+        None => ("i64.load", 8),
+        Some(Width::U8) => ("i64.load8_u", 1),
+        Some(Width::I8) => ("i64.load8_s", 1),
+        Some(Width::U16) => ("i64.load16_u", 2),
+        Some(Width::I16) => ("i64.load16_s", 2),
+        Some(Width::U32) => ("i64.load32_u", 4),
+        Some(Width::I32) => ("i64.load32_s", 4),
+    }
+}
+
+fn store(width: Option<Width>) -> (&'static str, usize) {
+    match width {
+        None => ("i64.store", 8),
+        Some(Width::U8 | Width::I8) => ("i64.store8", 1),
+        Some(Width::U16 | Width::I16) => ("i64.store16", 2),
+        Some(Width::U32 | Width::I32) => ("i64.store32", 4),
+    }
+}
+
+/// Translates functions and main, with the blocks labels start
+struct Translator<'a> {
+    out: Emitter,
+    current: Option<&'a Function>,
+    blocks: HashMap<Label, usize>,
+}
+
+impl<'a> Translator<'a> {
+    /// Sets `$sp` to the start of the arguments of the current function
+    /// minus `below` items
+    fn reset(&mut self, below: usize) {
+        self.out.emit("local.get $fp");
+        if below > 0 {
+            self.out.emit(&format!("i32.const {}", below * 8));
+            self.out.emit("i32.sub");
+        }
+        self.out.emit("global.set $sp");
+    }
+
+    /// Restarts the dispatch loop at the block of `label`
+    fn jump(&mut self, label: Label) {
+        self.out.emit(&format!("i32.const {}", self.blocks[&label]));
+        self.out.emit("local.set $pc");
+        self.out.emit("br $dispatch");
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::PushConst(v) => {
+                self.out.emit(&format!("i64.const {}", v));
+                self.out.emit("call $push");
+            }
+            Op::PushMem => {
+                self.out.emit(&format!("i64.const {}", MEM_BASE));
+                self.out.emit("call $push");
+            }
+            Op::PushLocal(i) => {
+                self.out.emit("local.get $fp");
+                self.out.emit(&format!("i32.const {}", (i + 1) * 8));
+                self.out.all(&["i32.sub", "i64.load", "call $push"]);
+            }
+            Op::Pick(n) => {
+                self.out.emit(&format!("i32.const {}", n));
+                self.out.emit("call $pick");
+            }
+            Op::PushReg(i) => {
+                self.out.emit(&format!("global.get $r{}", i));
+                self.out.emit("call $push");
+            }
+            Op::PopReg(i) => {
+                self.out.emit("call $pop");
+                self.out.emit(&format!("global.set $r{}", i));
+            }
+            Op::BinOp(op) => {
+                let instr = match op {
+                    BinOp::Add => "i64.add",
+                    BinOp::Sub => "i64.sub",
+                    BinOp::Less => "i64.lt_s",
+                    BinOp::Greater => "i64.gt_s",
+                    BinOp::Equal => "i64.eq",
+                    BinOp::NotEqual => "i64.ne",
+                };
+                self.out.all(&[
+                    "call $pop",
+                    "local.set $b",
+                    "call $pop",
+                    "local.get $b",
+                    instr,
+                ]);
+                if !matches!(op, BinOp::Add | BinOp::Sub) {
+                    self.out.emit("i64.extend_i32_u");
+                }
+                self.out.emit("call $push");
+            }
+            Op::Stack(StackOp::Dup) => self.out.all(&["i32.const 0", "call $pick"]),
+            Op::Stack(StackOp::Over) => self.out.all(&["i32.const 1", "call $pick"]),
+            Op::Stack(StackOp::Drop) => self.out.all(&["call $pop", "drop"]),
+            Op::Stack(StackOp::Swap) => self.out.all(&[
+                "call $pop",
+                "local.set $b",
+                "call $pop",
+                "local.set $a",
+                "local.get $b",
+                "call $push",
+                "local.get $a",
+                "call $push",
+            ]),
+            Op::Stack(StackOp::Rot) => self.out.all(&[
+                "call $pop",
+                "local.set $c",
+                "call $pop",
+                "local.set $b",
+                "call $pop",
+                "local.set $a",
+                "local.get $b",
+                "call $push",
+                "local.get $c",
+                "call $push",
+                "local.get $a",
+                "call $push",
+            ]),
+            // Wasm accesses don't need to be aligned
+            Op::Load(width) => {
+                let (instr, len) = load(*width);
+                self.out.emit("call $pop");
+                self.out.emit(&format!("i64.const {}", len));
+                self.out.all(&["call $address", instr, "call $push"]);
+            }
+            Op::Store(width) => {
+                let (instr, len) = store(*width);
+                self.out.all(&["call $pop", "local.set $b", "call $pop"]);
+                self.out.emit(&format!("i64.const {}", len));
+                self.out.all(&["call $address", "local.get $b", instr]);
+            }
+            Op::Print => self.out.all(&["call $pop", "call $print"]),
+            Op::Call { name, .. } => self.out.emit(&format!("call ${}", mangle(name))),
+            Op::CallExtern { .. } => {
+                unreachable!("extern calls are rejected before translating")
+            }
+            Op::TailCall { name, args } => {
+                for i in 0..*args {
+                    self.out.emit("local.get $fp");
+                    self.out.emit(&format!("i32.const {}", (i + 1) * 8));
+                    self.out.all(&["i32.sub", "global.get $sp"]);
+                    self.out.emit(&format!("i32.const {}", (i + 1) * 8));
+                    self.out.all(&["i32.sub", "i64.load", "i64.store"]);
+                }
+                self.reset(0);
+                match self.current {
+                    // Calling itself restarts the body in the same frame
+                    Some(f) if f.name == *name => {
+                        self.out
+                            .all(&["i32.const 0", "local.set $pc", "br $dispatch"])
+                    }
+                    _ => self.out.emit(&format!("return_call ${}", mangle(name))),
+                }
+            }
+            Op::Label(_) => {}
+            Op::Jump(l) => self.jump(*l),
+            Op::Branch(l) => {
+                self.out.all(&["call $pop", "i64.eqz", "if"]);
+                self.jump(*l);
+                self.out.emit("end");
+            }
+        }
+    }
+
+    /// Instructions of `body`, ops after a label go into its own block of
+    /// the dispatch loop, the first block holds the ops before any label
+    fn body(&mut self, body: &[Op]) {
+        let recurses = body.iter().any(|op| match (op, self.current) {
+            (Op::TailCall { name, .. }, Some(f)) => f.name == *name,
+            _ => false,
+        });
+        let starts: Vec<usize> = body
+            .iter()
+            .enumerate()
+            .filter_map(|(i, op)| match op {
+                Op::Label(l) => {
+                    self.blocks.insert(*l, self.blocks.len() + 1);
+                    Some(i)
+                }
+                _ => None,
+            })
+            .collect();
+        if starts.is_empty() && !recurses {
+            body.iter().for_each(|op| self.op(op));
+            return;
+        }
+
+        self.out.emit("loop $dispatch");
+        for i in (0..=starts.len()).rev() {
+            self.out.emit(&format!("block $b{}", i));
+        }
+        self.out.emit("local.get $pc");
+        let targets: Vec<String> = (0..=starts.len()).map(|i| format!("$b{}", i)).collect();
+        self.out.emit(&format!("br_table {}", targets.join(" ")));
+
+        let mut start = 0;
+        for end in starts.iter().copied().chain([body.len()]) {
+            self.out.emit("end");
+            body[start..end].iter().for_each(|op| self.op(op));
+            start = end;
+        }
+        self.out.emit("end");
+    }
+}
+
+fn function(function: &Function) -> String {
+    let mut translator = Translator {
+        out: Emitter::new(),
+        current: Some(function),
+        blocks: HashMap::new(),
+    };
+    translator.out.emit("global.get $sp");
+    translator.out.emit("local.set $fp");
+    translator.body(&function.body);
+    if function.returning {
+        translator.out.all(&["call $pop", "local.set $a"]);
+        translator.reset(function.params);
+        translator.out.all(&["local.get $a", "call $push"]);
+    } else {
+        translator.reset(function.params);
+    }
+    translator.out.wat
+}
+
+const LOCALS: &str = "    (local $fp i32)
+    (local $pc i32)
+    (local $a i64)
+    (local $b i64)
+    (local $c i64)
+";
+
+/// Translates `module` into a WebAssembly text module
+pub fn translate(module: &Module) -> String {
+    let stack = MEM_BASE as usize;
+    let pages = (stack + MEM_CAPACITY * 8).div_ceil(PAGE);
+
+    let mut wat = String::from("(module\n");
+    wat.push_str("  (import \"env\" \"print\" (func $print (param i64)))\n");
+    wat.push_str("  (import \"env\" \"memory_error\" (func $memory_error (param i64 i64)))\n");
+    writeln!(wat, "  (memory (export \"memory\") {})", pages).unwrap();
+    wat.push_str("  (global $sp (mut i32) (i32.const 0))\n");
+    for i in 1..=REGISTERS {
+        writeln!(wat, "  (global $r{} (mut i64) (i64.const 0))", i).unwrap();
+    }
+    wat.push_str(
+        &RUNTIME
+            .replace("{base}", &MEM_BASE.to_string())
+            .replace("{size}", &(MEM_CAPACITY * 8).to_string()),
+    );
+
+    for f in module.functions.iter() {
+        writeln!(wat, "  (func ${}", mangle(&f.name)).unwrap();
+        wat.push_str(LOCALS);
+        wat.push_str(&function(f));
+        wat.push_str("  )\n");
+    }
+
+    wat.push_str("  (func (export \"main\")\n");
+    wat.push_str(LOCALS);
+    let mut translator = Translator {
+        out: Emitter::new(),
+        current: None,
+        blocks: HashMap::new(),
+    };
+    translator.body(&module.main);
+    wat.push_str(&translator.out.wat);
+    wat.push_str("  )\n)\n");
+    wat
+}
@@ -0,0 +1,411 @@
+//! NASM assembly for x86_64 Linux.
+//!
+//! Stack items live on the machine stack. Functions keep `rbp` pointing at
+//! their frame and read their arguments above the return address, callers
+//! drop the arguments and push the result from `rax`.
+//!
+//! Assembly is generated for NASM, and translated for GNU as at the end.
+//!
+//! Object files are position independent, so they link into PIE programs,
+//! and export every `export fn` through a wrapper taking its arguments in
+//! the System V ABI registers.
+
+use std::io::{Result, Write};
+
+use super::{c::mangle, cache::TopOfStack, gas, peephole, Assembler, Options, Output, Target};
+use crate::{
+    interpreter::REGISTERS,
+    ir::{BinOp, Function, Op, StackOp},
+    lexer::tokens::Width,
+    lib::constants::{EXTERN_PARAMS, MEM_CAPACITY},
+};
+
+/// NASM assembly for x86_64 Linux
+pub struct X86_64 {
+    mem_capacity: usize,
+    opt_level: u8,
+    assembler: Assembler,
+    libc: bool,
+    /// Building an object file, with position independent code
+    library: bool,
+}
+
+impl X86_64 {
+    pub fn new(options: &Options) -> Self {
+        Self {
+            mem_capacity: MEM_CAPACITY,
+            opt_level: options.opt_level,
+            assembler: options.assembler,
+            libc: options.libc,
+            library: options.output != Output::Exe,
+        }
+    }
+
+    fn op_to_asm(&self, op: &Op) -> String {
+        match op {
+            Op::BinOp(BinOp::Add) => {
+                "\t; Plus\n\tpop  rax\n\tpop  rbx\n\tadd  rax, rbx\n\tpush rax\n".to_string()
+            }
+            Op::BinOp(BinOp::Sub) => {
+                "\t; Minus\n\tpop  rax\n\tpop  rbx\n\tsub  rbx, rax\n\tpush rbx\n".to_string()
+            }
+            Op::BinOp(BinOp::Less) => {
+                "\t; Less\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rbx\n\tpop rax\n\tcmp rax, rbx\n\tcmovl rcx, rdx\n\tpush rcx\n".to_string()
+            }
+            Op::BinOp(BinOp::Greater) => {
+                "\t; Greater\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rbx\n\tpop rax\n\tcmp rax, rbx\n\tcmovg rcx, rdx\n\tpush rcx\n".to_string()
+            }
+            Op::BinOp(BinOp::Equal) => {
+                "\t; Equal\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rax\n\tpop rbx\n\tcmp rax, rbx\n\tcmove rcx, rdx\n\tpush rcx\n".to_string()
+            }
+            Op::BinOp(BinOp::NotEqual) => {
+                "\t; NotEqual\n\tmov rcx, 0\n\tmov rdx, 1\n\tpop rax\n\tpop rbx\n\tcmp rax, rbx\n\tcmovne rcx, rdx\n\tpush rcx\n".to_string()
+            }
+            Op::Print => "\t; Dot\n\tpop  rdi\n\tcall print\n".to_string(),
+            Op::Stack(StackOp::Dup) => "\t; DUP\n\tpop rax\n\tpush rax\n\tpush rax\n".to_string(),
+            Op::Stack(StackOp::Drop) if self.opt_level >= 1 => "\t; DROP\n\tadd rsp, 8\n".to_string(),
+            Op::Stack(StackOp::Drop) => "\t; DROP\n\tpop rax\n\txor rax, rax\n".to_string(),
+            Op::Stack(StackOp::Swap) => {
+                "\t; SWAP\n\tpop rax\n\tpop rbx\n\tpush rax\n\tpush rbx\n".to_string()
+            }
+            Op::Stack(StackOp::Over) => {
+                "\t; OVER\n\tpop rax\n\tpop rbx\n\tpush rbx\n\tpush rax\n\tpush rbx\n".to_string()
+            }
+            Op::Stack(StackOp::Rot) => {
+                "\t; ROT\n\tpop rax\n\tpop rbx\n\tpop rcx\n\tpush rbx\n\tpush rax\n\tpush rcx\n".to_string()
+            }
+            Op::Label(l) => format!(".L{}:\n", l),
+            Op::Jump(l) => format!("\tjmp .L{}\n", l),
+            Op::Branch(l) => format!("\t; Branch\n\tpop rax\n\ttest rax, rax\n\tjz .L{}\n", l),
+            Op::Call {
+                name,
+                args,
+                returning,
+            } => format!(
+                "\t; Call {0}\n\tcall {0}\n\tadd rsp, {1}\n{2}",
+                name,
+                args * 8,
+                if *returning { "\tpush rax\n" } else { "" }
+            ),
+            Op::CallExtern {
+                name,
+                args,
+                returning,
+            } => format!(
+                "\t; Call extern {}\n{}{}",
+                name,
+                call_extern(name, *args, self.library),
+                if *returning { "\tpush rax\n" } else { "" }
+            ),
+            Op::TailCall { name, args } => format!("\t; Tail call {}\n{}", name, tail_call(name, *args)),
+            // Arguments sit above the return address and the saved `rbp`
+            Op::PushLocal(i) => format!(
+                "\t; Push local {}\n\tmov rax, [rbp + {}]\n\tpush rax\n",
+                i,
+                16 + i * 8
+            ),
+            Op::Pick(n) => format!("\t; Pick {}\n\tpush QWORD [rsp + {}]\n", n, n * 8),
+            Op::PushMem if self.library => "\t; MEM\n\tlea rax, [mem]\n\tpush rax\n".to_string(),
+            Op::PushMem => "\t; MEM\n\tpush mem\n".to_string(),
+            Op::PushConst(n) => format!("\t; Push {0}\n\tmov  rax, {0}\n\tpush rax\n", n),
+            Op::Load(None) => {
+                "\t; Load\n\tpop rax\n\txor rbx, rbx\n\tmov rbx, [rax]\n\tpush rbx\n".to_string()
+            }
+            Op::Load(Some(width)) => {
+                let load = match width {
+                    Width::U8 => "movzx rbx, BYTE [rax]",
+                    Width::I8 => "movsx rbx, BYTE [rax]",
+                    Width::U16 => "movzx rbx, WORD [rax]",
+                    Width::I16 => "movsx rbx, WORD [rax]",
+                    Width::U32 => "mov ebx, DWORD [rax]",
+                    Width::I32 => "movsxd rbx, DWORD [rax]",
+                };
+                format!("\t; Load {:?}\n\tpop rax\n\t{}\n\tpush rbx\n", width, load)
+            }
+            Op::PushReg(i) => format!("\t; Push {0}\n\tpush {0}\n", register(*i)),
+            Op::Store(None) => "\t; Store\n\tpop rax\n\tpop rbx\n\tmov [rbx], rax\n".to_string(),
+            Op::Store(Some(width)) => {
+                let reg = match width.bytes() {
+                    1 => "al",
+                    2 => "ax",
+                    _ => "eax",
+                };
+                format!("\t; Store {:?}\n\tpop rax\n\tpop rbx\n\tmov [rbx], {}\n", width, reg)
+            }
+            Op::PopReg(i) => format!("\t; Pop {0}\n\tpop {0}\n", register(*i)),
+        }
+    }
+
+    /// Label the program starts at, libc's `_start` calls `main`
+    fn entry_point(&self) -> &'static str {
+        if self.libc {
+            "main"
+        } else {
+            "_start"
+        }
+    }
+
+    fn peephole(&self, asm: String) -> String {
+        if self.opt_level >= 1 {
+            peephole::optimize(&asm)
+        } else {
+            asm
+        }
+    }
+}
+
+impl Target for X86_64 {
+    fn comment(&self) -> &'static str {
+        ";"
+    }
+
+    fn body(&self, ops: &[Op]) -> String {
+        if self.opt_level >= 2 {
+            let mut cached = TopOfStack::new(self.library);
+            ops.iter().for_each(|op| cached.op(op));
+            return self.peephole(cached.into_asm());
+        }
+        self.peephole(ops.iter().map(|op| self.op_to_asm(op)).collect())
+    }
+
+    fn function(&self, function: &Function) -> String {
+        let mut asm = format!("{}:\n\tpush rbp\n\tmov rbp, rsp\n", function.name);
+        if self.opt_level >= 2 {
+            let mut cached = TopOfStack::new(self.library);
+            function.body.iter().for_each(|op| cached.op(op));
+            asm.push_str(&cached.ret(function.returning));
+        } else {
+            for op in function.body.iter() {
+                asm.push_str(&self.op_to_asm(op));
+            }
+            if function.returning {
+                asm.push_str("\n\tpop rax\n");
+            }
+        }
+        asm.push_str("\tmov rsp, rbp\n\tpop rbp\n\tret\n");
+        self.peephole(asm)
+    }
+
+    fn externs(&self, names: &[String], handler: &mut dyn Write) -> Result<()> {
+        for name in names {
+            writeln!(handler, "extern {}", name)?;
+        }
+
+        Ok(())
+    }
+
+    fn headers(&self, handler: &mut dyn Write) -> Result<()> {
+        if self.library {
+            // Symbols in memory operands are addressed relative to `rip`
+            writeln!(handler, "default rel")?;
+        } else {
+            writeln!(handler, "global {}", self.entry_point())?;
+        }
+        writeln!(handler, "section .text")?;
+
+        // Print function, writes the digits of the magnitude backwards from
+        // the end of its frame and a `-` before them for negative values
+        writeln!(handler, "print:")?;
+        writeln!(handler, "\tsub     rsp, 40")?;
+        writeln!(handler, "\tmov     rsi, rdi")?;
+        writeln!(handler, "\tmov     r11, rdi")?;
+        // Labels starting with `.` are local to the previous label in NASM
+        // but global in GNU as, where `.L2` could be a program label
+        let (digit, positive, unsigned) = match self.assembler {
+            Assembler::Nasm => (".L2", ".L3", ".L4"),
+            Assembler::Gas => (".Lprint", ".Lprint_positive", ".Lprint_unsigned"),
+        };
+        writeln!(handler, "\ttest    rsi, rsi")?;
+        writeln!(handler, "\tjns     {}", unsigned)?;
+        // The magnitude of the smallest value only fits unsigned
+        writeln!(handler, "\tneg     rsi")?;
+        writeln!(handler, "{}:", unsigned)?;
+        writeln!(handler, "\tmov     r10, -3689348814741910323")?;
+        writeln!(handler, "\tmov     BYTE [rsp+39], 10")?;
+        writeln!(handler, "\tlea     rcx, [rsp+38]")?;
+        writeln!(handler, "\tlea     r8, [rsp+40]")?;
+        writeln!(handler, "{}:", digit)?;
+        writeln!(handler, "\tmov     rax, rsi")?;
+        writeln!(handler, "\tmov     r9, r8")?;
+        writeln!(handler, "\tmul     r10")?;
+        writeln!(handler, "\tmov     rax, rsi")?;
+        writeln!(handler, "\tsub     r9, rcx")?;
+        writeln!(handler, "\tshr     rdx, 3")?;
+        writeln!(handler, "\tlea     rdi, [rdx+rdx*4]")?;
+        writeln!(handler, "\tadd     rdi, rdi")?;
+        writeln!(handler, "\tsub     rax, rdi")?;
+        writeln!(handler, "\tadd     eax, 48")?;
+        writeln!(handler, "\tmov     BYTE [rcx], al")?;
+        writeln!(handler, "\tmov     rax, rsi")?;
+        writeln!(handler, "\tmov     rsi, rdx")?;
+        writeln!(handler, "\tmov     rdx, rcx")?;
+        writeln!(handler, "\tsub     rcx, 1")?;
+        writeln!(handler, "\tcmp     rax, 9")?;
+        writeln!(handler, "\tja      {}", digit)?;
+        writeln!(handler, "\ttest    r11, r11")?;
+        writeln!(handler, "\tjns     {}", positive)?;
+        writeln!(handler, "\tsub     rdx, 1")?;
+        writeln!(handler, "\tmov     BYTE [rdx], 45")?;
+        writeln!(handler, "\tadd     r9, 1")?;
+        writeln!(handler, "{}:", positive)?;
+        writeln!(handler, "\tsub     rdx, r8")?;
+        writeln!(handler, "\tmov     edi, 1")?;
+        writeln!(handler, "\txor     eax, eax")?;
+        writeln!(handler, "\tlea     rsi, [rsp+40+rdx]")?;
+        writeln!(handler, "\tmov     rdx, r9")?;
+        writeln!(handler, "\tmov     rax, 1")?;
+        writeln!(handler, "\tsyscall")?;
+        writeln!(handler, "\tadd     rsp, 40")?;
+        writeln!(handler, "\tret")?;
+
+        Ok(())
+    }
+
+    fn entry(&self, handler: &mut dyn Write) -> Result<()> {
+        writeln!(handler, "{}:", self.entry_point())?;
+        for reg in USER_REGISTERS[..REGISTERS - SPILLED_REGISTERS].iter() {
+            writeln!(handler, "\txor {0}, {0}", reg)?;
+        }
+
+        Ok(())
+    }
+
+    fn exports(&self, functions: &[&Function], handler: &mut dyn Write) -> Result<()> {
+        let kept = &USER_REGISTERS[..REGISTERS - SPILLED_REGISTERS];
+        for function in functions {
+            let name = mangle(&function.name);
+            writeln!(handler, "global {}", name)?;
+            writeln!(handler, "{}:", name)?;
+            for reg in CALLEE_SAVED.iter() {
+                writeln!(handler, "\tpush {}", reg)?;
+            }
+            // Program registers kept in callee-saved registers live in
+            // memory between calls
+            for (i, reg) in kept.iter().enumerate() {
+                writeln!(handler, "\tmov {}, [saved + {}]", reg, i * 8)?;
+            }
+            // The first argument ends up on top of the stack
+            for reg in ARGUMENT_REGISTERS[..function.params].iter().rev() {
+                writeln!(handler, "\tpush {}", reg)?;
+            }
+            writeln!(handler, "\tcall {}", function.name)?;
+            if function.params > 0 {
+                writeln!(handler, "\tadd rsp, {}", function.params * 8)?;
+            }
+            for (i, reg) in kept.iter().enumerate() {
+                writeln!(handler, "\tmov [saved + {}], {}", i * 8, reg)?;
+            }
+            for reg in CALLEE_SAVED.iter().rev() {
+                writeln!(handler, "\tpop {}", reg)?;
+            }
+            writeln!(handler, "\tret")?;
+            writeln!(handler)?;
+        }
+
+        Ok(())
+    }
+
+    fn footers(&self, handler: &mut dyn Write) -> Result<()> {
+        // Exported functions return to their caller instead of exiting
+        if !self.library {
+            if self.libc {
+                // `exit` flushes the buffers of stdio
+                writeln!(handler, "\txor edi, edi")?;
+                writeln!(handler, "\tand rsp, -16")?;
+                writeln!(handler, "\tcall exit")?;
+            } else {
+                writeln!(handler, "\tmov rax, 60")?;
+                writeln!(handler, "\tmov rdi, 0")?;
+                writeln!(handler, "\tsyscall")?;
+                writeln!(handler, "\tret")?;
+            }
+        }
+
+        writeln!(handler, "section .bss")?;
+        writeln!(handler, "\tmem resq {}", self.mem_capacity)?;
+        writeln!(handler, "\tregs resq {}", SPILLED_REGISTERS)?;
+        if self.library {
+            writeln!(handler, "\tsaved resq {}", REGISTERS - SPILLED_REGISTERS)?;
+            // Linking C programs against the object keeps their stack
+            // non-executable
+            writeln!(
+                handler,
+                "section .note.GNU-stack noalloc noexec nowrite progbits"
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&self, asm: String) -> String {
+        match self.assembler {
+            Assembler::Nasm => asm,
+            Assembler::Gas => gas::translate(&asm),
+        }
+    }
+}
+
+/// Operands holding the program registers. Generated code and `print` never
+/// use the callee-saved `r12` to `r15` as scratch, so builtins leave them
+/// alone, the remaining registers live in memory
+const USER_REGISTERS: [&str; REGISTERS] = [
+    "r12",
+    "r13",
+    "r14",
+    "r15",
+    "QWORD [regs]",
+    "QWORD [regs + 8]",
+    "QWORD [regs + 16]",
+    "QWORD [regs + 24]",
+];
+
+/// Number of program registers kept in memory
+const SPILLED_REGISTERS: usize = 4;
+
+/// Operand holding the program register with the given index, indices are
+/// checked when lowering
+pub(super) fn register(idx: usize) -> &'static str {
+    USER_REGISTERS[idx - 1]
+}
+
+/// Integer argument registers of the System V ABI, in order
+const ARGUMENT_REGISTERS: [&str; EXTERN_PARAMS] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+/// Registers the System V ABI has functions preserve, generated code uses
+/// `rbx` as scratch and the program registers live in the others
+const CALLEE_SAVED: [&str; 6] = ["rbx", "rbp", "r12", "r13", "r14", "r15"];
+
+/// Pops the `args` arguments on top of the stack into the argument registers
+/// and calls the C function `name` with the machine stack aligned to 16
+/// bytes, its result is left in `rax`. The callee-saved `rbx` keeps the
+/// unaligned stack pointer, and `al` tells variadic functions like `printf`
+/// that no vector registers are used. Position independent code calls
+/// through the PLT, since `name` may be in a shared library
+pub(super) fn call_extern(name: &str, args: usize, pic: bool) -> String {
+    let mut asm = String::new();
+    for reg in ARGUMENT_REGISTERS[..args].iter() {
+        asm.push_str(&format!("\tpop {}\n", reg));
+    }
+    let plt = if pic { " wrt ..plt" } else { "" };
+    asm.push_str(&format!(
+        "\tmov rbx, rsp\n\tand rsp, -16\n\txor eax, eax\n\tcall {}{}\n\tmov rsp, rbx\n",
+        name, plt
+    ));
+    asm
+}
+
+/// Moves the `args` arguments on top of the stack over the arguments of the
+/// current function, drops its frame and jumps to `name`, which then returns
+/// to the caller of the current function
+pub(super) fn tail_call(name: &str, args: usize) -> String {
+    let mut asm = String::new();
+    for i in 0..args {
+        asm.push_str(&format!(
+            "\tmov rax, [rsp + {}]\n\tmov [rbp + {}], rax\n",
+            i * 8,
+            16 + i * 8
+        ));
+    }
+    asm.push_str(&format!("\tmov rsp, rbp\n\tpop rbp\n\tjmp {}\n", name));
+    asm
+}
@@ -0,0 +1,303 @@
+use std::{
+    error::Error,
+    io::{stdout, Stdout, Write},
+    path::Path,
+};
+
+use rustyline::{error::ReadlineError, Editor};
+
+use crate::{
+    interpreter::{format_stack, Interpreter},
+    lexer::tokenize,
+    lib::{
+        errors::ReplError,
+        utils::{LocatedResult, Location},
+    },
+};
+
+const HELP: &str = "\
+break <[file:]row>  stop before executing tokens of a row (b)
+delete [n]          delete breakpoint n, or all of them (d)
+breakpoints         list breakpoints
+continue            run until a breakpoint or the end of the program (c, run)
+step                execute one token, entering function calls (s)
+next                execute one token, stepping over function calls (n)
+finish              run until the current function returns (f)
+stack               print the data stack
+locals              print locals of the current frame
+regs                print registers
+mem <addr> <len>    dump <len> bytes of memory starting at <addr> (e.g. `mem+8`)
+backtrace           print active function calls (bt)
+list                show the token about to be executed (l)
+restart             start the program over, keeping breakpoints
+help                show this message
+quit                exit the debugger (q)
+An empty line repeats the previous command.";
+
+struct Breakpoint {
+    file: String,
+    row: usize,
+}
+
+/// Interactive step-through debugger driving an `Interpreter`, the program
+/// and the debugger both write to its output
+pub struct Debugger<W: Write> {
+    interpreter: Interpreter<W>,
+    path: String,
+    source: String,
+    breakpoints: Vec<Breakpoint>,
+    editor: Editor<()>,
+}
+
+impl Debugger<Stdout> {
+    pub fn new(path: &str, source: String) -> LocatedResult<Self> {
+        Self::with_output(path, source, stdout())
+    }
+}
+
+impl<W: Write> Debugger<W> {
+    pub fn with_output(path: &str, source: String, output: W) -> LocatedResult<Self> {
+        let mut debugger = Self {
+            interpreter: Interpreter::new(output),
+            path: path.to_string(),
+            source,
+            breakpoints: Vec::new(),
+            editor: Editor::new(),
+        };
+        debugger.restart()?;
+        Ok(debugger)
+    }
+
+    fn restart(&mut self) -> LocatedResult<()> {
+        self.interpreter.reset();
+        self.interpreter.load(tokenize(&self.source, &self.path)?)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn output(&mut self) -> &mut W {
+        self.interpreter.output()
+    }
+
+    fn print_position(&mut self) {
+        let loc = match self.interpreter.next_token() {
+            Some(token) => token.loc.clone(),
+            None => {
+                writeln!(self.interpreter.output(), "program finished").unwrap();
+                return;
+            }
+        };
+        let out = self.interpreter.output();
+        writeln!(out, "{}", loc).unwrap();
+        if loc.file == self.path {
+            if let Some(line) = self.source.lines().nth(loc.row - 1) {
+                let padding: String = line
+                    .chars()
+                    .take(loc.col - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(out, "\t{}\n\t{}^", line, padding).unwrap();
+            }
+        }
+    }
+
+    /// Index of the breakpoint the next token stops at. A row is entered when
+    /// execution reaches it from another row of the same frame, calls into it
+    /// or returns to it from a call made on a different row
+    fn breakpoint(&self, from: &Option<(usize, Location)>) -> Option<usize> {
+        let loc = &self.interpreter.next_token()?.loc;
+        let depth = self.interpreter.depth();
+        let prev = match from {
+            Some((d, prev)) if *d == depth => Some(prev),
+            Some((d, _)) if *d > depth => self.interpreter.previous_token().map(|t| &t.loc),
+            _ => None,
+        };
+        if prev.is_some_and(|p| p.row == loc.row && p.file == loc.file) {
+            return None;
+        }
+        self.breakpoints
+            .iter()
+            .position(|b| b.row == loc.row && Path::new(&loc.file).ends_with(&b.file))
+    }
+
+    /// Steps at least once, then until `stop` holds, a breakpoint is reached
+    /// or the program ends
+    fn advance<F>(&mut self, stop: F)
+    where
+        F: Fn(&Interpreter<W>) -> bool,
+    {
+        if !self.interpreter.is_running() {
+            let out = self.interpreter.output();
+            writeln!(out, "program is not running, use `restart`").unwrap();
+            return;
+        }
+
+        loop {
+            let from = self
+                .interpreter
+                .next_token()
+                .map(|t| (self.interpreter.depth(), t.loc.clone()));
+            if let Err(e) = self.interpreter.step() {
+                writeln!(self.interpreter.output(), "{}\nprogram terminated", e).unwrap();
+                return;
+            }
+            if !self.interpreter.is_running() || stop(&self.interpreter) {
+                break;
+            }
+            if let Some(idx) = self.breakpoint(&from) {
+                writeln!(self.interpreter.output(), "breakpoint #{}", idx + 1).unwrap();
+                break;
+            }
+        }
+        self.print_position();
+    }
+
+    fn parse_breakpoint(&self, arg: &str) -> Option<Breakpoint> {
+        let (file, row) = match arg.rsplit_once(':') {
+            Some((file, row)) => (file.to_string(), row),
+            None => (self.path.clone(), arg),
+        };
+        Some(Breakpoint {
+            file,
+            row: row.parse().ok().filter(|r| *r > 0)?,
+        })
+    }
+
+    /// Executes a debugger command, returns `false` if the debugger should exit
+    pub(crate) fn command(&mut self, line: &str) -> Result<bool, Box<dyn Error>> {
+        let mut args = line.split_whitespace();
+        let name = args.next().unwrap_or("");
+        let args: Vec<&str> = args.collect();
+        let missing = || ReplError::MissingArgument(name.to_string());
+
+        match name {
+            "break" | "b" => {
+                let arg = args.first().ok_or_else(missing)?;
+                let bp = self
+                    .parse_breakpoint(arg)
+                    .ok_or_else(|| ReplError::InvalidArgument(arg.to_string()))?;
+                writeln!(
+                    self.interpreter.output(),
+                    "breakpoint #{} at {}:{}",
+                    self.breakpoints.len() + 1,
+                    bp.file,
+                    bp.row
+                )?;
+                self.breakpoints.push(bp);
+            }
+            "delete" | "d" => match args.first() {
+                Some(arg) => {
+                    let idx = arg
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|i| (1..=self.breakpoints.len()).contains(i))
+                        .ok_or_else(|| ReplError::InvalidArgument(arg.to_string()))?;
+                    self.breakpoints.remove(idx - 1);
+                }
+                None => self.breakpoints.clear(),
+            },
+            "breakpoints" => {
+                let out = self.interpreter.output();
+                for (i, bp) in self.breakpoints.iter().enumerate() {
+                    writeln!(out, "#{} {}:{}", i + 1, bp.file, bp.row)?;
+                }
+            }
+            "continue" | "c" | "run" | "r" => self.advance(|_| false),
+            "step" | "s" => self.advance(|_| true),
+            "next" | "n" => {
+                let depth = self.interpreter.depth();
+                self.advance(|i| i.depth() <= depth);
+            }
+            "finish" | "f" => {
+                let depth = self.interpreter.depth();
+                self.advance(|i| i.depth() < depth);
+            }
+            "stack" => {
+                let stack = format_stack(self.interpreter.stack());
+                writeln!(self.interpreter.output(), "{}", stack)?;
+            }
+            "locals" => {
+                let locals = self.interpreter.locals().to_vec();
+                for (name, v) in locals {
+                    writeln!(self.interpreter.output(), "{} = {}", name, v)?;
+                }
+            }
+            "regs" => {
+                let registers = *self.interpreter.registers();
+                for (i, v) in registers.iter().enumerate() {
+                    writeln!(self.interpreter.output(), "r{} = {}", i + 1, v)?;
+                }
+            }
+            "mem" => {
+                let addr = args.first().ok_or_else(missing)?;
+                let addr = self
+                    .interpreter
+                    .memory()
+                    .parse_address(addr)
+                    .ok_or_else(|| ReplError::InvalidArgument(addr.to_string()))?;
+                let len = args.get(1).ok_or_else(missing)?;
+                let len = len
+                    .parse::<usize>()
+                    .map_err(|_| ReplError::InvalidArgument(len.to_string()))?;
+                let dump = self.interpreter.memory().dump(addr, len)?;
+                write!(self.interpreter.output(), "{}", dump)?;
+            }
+            "backtrace" | "bt" => {
+                for (i, (name, loc)) in self.interpreter.backtrace().iter().enumerate() {
+                    writeln!(self.interpreter.output(), "#{} {} at {}", i, name, loc)?;
+                }
+            }
+            "list" | "l" => self.print_position(),
+            "restart" => {
+                self.restart()?;
+                self.print_position();
+            }
+            "help" => writeln!(self.interpreter.output(), "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            // Debugger commands have no `:` prefix, unlike the shell's
+            other => {
+                return Err(format!(
+                    "UnknownCommandError: `{}` is not a command, see `help`",
+                    other
+                )
+                .into())
+            }
+        }
+        Ok(true)
+    }
+
+    /// Runs the debugger until `quit` or end of input, returns the exit status
+    pub fn run_loop(&mut self) -> i32 {
+        let out = self.interpreter.output();
+        writeln!(out, "debugging {}, type `help` for commands", self.path).unwrap();
+        self.print_position();
+
+        let mut last = String::new();
+        loop {
+            let line = match self.editor.readline("(lcl) ") {
+                Ok(line) => line,
+                Err(ReadlineError::Eof) => return 0,
+                Err(ReadlineError::Interrupted) => return 130,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return 1;
+                }
+            };
+            let line = if line.trim().is_empty() {
+                last.clone()
+            } else {
+                self.editor.add_history_entry(line.as_str());
+                line.trim().to_string()
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            match self.command(&line) {
+                Ok(true) => {}
+                Ok(false) => return 0,
+                Err(e) => eprintln!("{}", e),
+            }
+            last = line;
+        }
+    }
+}
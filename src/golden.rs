@@ -0,0 +1,277 @@
+use std::{
+    env, fmt,
+    fs::{self, read_dir, remove_file},
+    io,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    str::from_utf8,
+};
+
+use crate::{
+    compiler::{compile, Assembler, Options},
+    interpreter::Interpreter,
+    lexer::tokenize,
+};
+
+const EXPECT: &str = "// expect:";
+
+/// Where the expected output of a program comes from
+enum Expectation {
+    /// Companion `.out` file
+    File(PathBuf, String),
+    /// `// expect:` comments, one per line of output
+    Comments(String),
+    Missing,
+}
+
+impl Expectation {
+    fn of(path: &Path, src: &str) -> io::Result<Self> {
+        let out = path.with_extension("out");
+        if out.exists() {
+            return Ok(Self::File(out.clone(), fs::read_to_string(out)?));
+        }
+        let lines = expected_lines(src);
+        if lines.is_empty() {
+            Ok(Self::Missing)
+        } else {
+            Ok(Self::Comments(
+                lines.iter().map(|l| format!("{}\n", l)).collect(),
+            ))
+        }
+    }
+
+    fn output(&self) -> Option<&str> {
+        match self {
+            Self::File(_, s) | Self::Comments(s) => Some(s),
+            Self::Missing => None,
+        }
+    }
+}
+
+/// Text of every `// expect:` comment in `src`, in order
+pub fn expected_lines(src: &str) -> Vec<&str> {
+    src.lines()
+        .filter_map(|line| line.split_once(EXPECT))
+        .map(|(_, text)| text.strip_prefix(' ').unwrap_or(text))
+        .collect()
+}
+
+/// Rewrites `// expect:` comments of `src` to expect `output`. Comments are
+/// updated in place when the number of lines still matches, otherwise they
+/// are removed and new ones are appended to the end of the program
+pub fn bless_comments(src: &str, output: &str) -> String {
+    let mut expected = output.lines();
+    let mut res = String::new();
+
+    if expected_lines(src).len() == output.lines().count() {
+        for line in src.lines() {
+            match line.split_once(EXPECT) {
+                Some((code, _)) => res.push_str(&format!(
+                    "{}{} {}\n",
+                    code,
+                    EXPECT,
+                    expected.next().unwrap()
+                )),
+                None => res.push_str(&format!("{}\n", line)),
+            }
+        }
+        return res;
+    }
+
+    for line in src.lines() {
+        match line.split_once(EXPECT) {
+            Some((code, _)) if code.trim().is_empty() => {}
+            Some((code, _)) => res.push_str(&format!("{}\n", code.trim_end())),
+            None => res.push_str(&format!("{}\n", line)),
+        }
+    }
+    for line in expected {
+        res.push_str(&format!("{} {}\n", EXPECT, line));
+    }
+    res
+}
+
+/// Line diff turning `expected` into `got`, removed lines are marked with
+/// `-` and added ones with `+`
+pub fn diff(expected: &str, got: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = got.lines().collect();
+
+    // Longest common subsequence of lines, from the back
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut res = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            res.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        } else {
+            res.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        }
+    }
+    res
+}
+
+/// Every `.lcl` file under `path`, sorted, or `path` itself if it is a file
+pub fn discover(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut programs = Vec::new();
+    for entry in read_dir(path)? {
+        let entry = entry?.path();
+        if entry.is_dir() {
+            programs.extend(discover(&entry)?);
+        } else if entry.extension().is_some_and(|ext| ext == "lcl") {
+            programs.push(entry);
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+fn run_native(path: &str, src: &str, id: usize) -> Result<String, String> {
+    let tokens = tokenize(src, path).map_err(|e| e.to_string())?;
+    let out = env::temp_dir().join(format!("lcl-test-{}-{}", process::id(), id));
+    let out = out.to_str().unwrap();
+
+    let options = Options {
+        assembler: Assembler::detect(),
+        ..Default::default()
+    };
+    let compiled = compile(&tokens, out, &options);
+    let _ = remove_file(format!("{}.asm", out));
+    let _ = remove_file(format!("{}.s", out));
+    let _ = remove_file(format!("{}.o", out));
+    compiled.map_err(|e| e.to_string())?;
+
+    let output = Command::new(out).output();
+    let _ = remove_file(out);
+    let output = output.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("program exited with {}", output.status));
+    }
+    Ok(from_utf8(&output.stdout).unwrap().to_string())
+}
+
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    blessed: usize,
+}
+
+impl Summary {
+    fn fail(&mut self, name: &str, reason: impl fmt::Display) {
+        println!("FAIL {}\n\t{}", name, reason);
+        self.failed += 1;
+    }
+}
+
+/// Compares the output of `program` with its expectation and runs its tests
+fn check(
+    program: &Path,
+    id: usize,
+    native: bool,
+    bless: bool,
+    summary: &mut Summary,
+) -> io::Result<()> {
+    let name = program.to_str().unwrap();
+    let src = fs::read_to_string(program)?;
+    let expectation = Expectation::of(program, &src)?;
+
+    let mut interpreter = Interpreter::new(Vec::new());
+    if let Err(e) = tokenize(&src, name).and_then(|tokens| interpreter.load(tokens)) {
+        summary.fail(name, e);
+        return Ok(());
+    }
+    let tests = interpreter.tests().to_vec();
+
+    // Programs made only of tests need no expected output
+    if tests.is_empty() || expectation.output().is_some() {
+        let output = if native {
+            run_native(name, &src, id)
+        } else {
+            interpreter
+                .run()
+                .map(|()| from_utf8(interpreter.output()).unwrap().to_string())
+                .map_err(|e| e.to_string())
+        };
+
+        match output {
+            Ok(output) if expectation.output() == Some(output.as_str()) => summary.passed += 1,
+            Ok(output) if bless => {
+                match &expectation {
+                    Expectation::Comments(_) => fs::write(program, bless_comments(&src, &output))?,
+                    Expectation::File(out, _) => fs::write(out, &output)?,
+                    Expectation::Missing => fs::write(program.with_extension("out"), &output)?,
+                }
+                println!("BLESS {}", name);
+                summary.blessed += 1;
+            }
+            Ok(output) => match expectation.output() {
+                Some(expected) => {
+                    print!("FAIL {}\n{}", name, diff(expected, &output));
+                    summary.failed += 1;
+                }
+                None => summary.fail(name, "no expected output, run with --bless"),
+            },
+            Err(e) => summary.fail(name, e),
+        }
+    }
+
+    for test in tests.iter() {
+        match interpreter.run_test(test) {
+            Ok(()) => summary.passed += 1,
+            Err(e) => summary.fail(&format!("{} \"{}\"", test.loc, test.name), e),
+        }
+    }
+    Ok(())
+}
+
+/// Runs every program under `path`, compares its output with the expected
+/// one and runs its tests, returns the exit status
+pub fn run(path: &Path, native: bool, bless: bool) -> i32 {
+    let programs = match discover(path) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    let mut summary = Summary::default();
+    for (id, program) in programs.iter().enumerate() {
+        if let Err(e) = check(program, id, native, bless, &mut summary) {
+            summary.fail(program.to_str().unwrap(), e);
+        }
+    }
+
+    print!("{} passed, {} failed", summary.passed, summary.failed);
+    if bless {
+        print!(", {} blessed", summary.blessed);
+    }
+    println!();
+
+    if summary.failed > 0 {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+use crate::{
+    lexer::tokens::Width,
+    lib::{
+        constants::{MEM_BASE, MEM_CAPACITY},
+        errors::MemoryError,
+    },
+};
+
+/// Byte-addressed simulation of the `mem` block of a compiled program.
+///
+/// Addresses are absolute: `base()` is what `mem` pushes, and every access
+/// is checked to lie entirely within `[base, base + capacity)`.
+///
+/// While a journal is open every write remembers the bytes it overwrote,
+/// so the writes can be undone with `rollback`.
+#[derive(Clone)]
+pub struct Memory {
+    data: Vec<u8>,
+    base: i64,
+    journal: Option<Vec<(usize, Vec<u8>)>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0; MEM_CAPACITY * 8],
+            base: MEM_BASE,
+            journal: None,
+        }
+    }
+
+    /// Starts recording writes
+    pub fn begin(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stops recording writes, keeping them
+    pub fn commit(&mut self) {
+        self.journal = None;
+    }
+
+    /// Undoes every write made since `begin`
+    pub fn rollback(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            for (start, bytes) in journal.into_iter().rev() {
+                self.data[start..start + bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+    }
+
+    fn write(&mut self, range: Range<usize>, bytes: &[u8]) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((range.start, self.data[range.clone()].to_vec()));
+        }
+        self.data[range].copy_from_slice(bytes);
+    }
+
+    pub fn base(&self) -> i64 {
+        self.base
+    }
+
+    fn range(&self, addr: i64, len: usize) -> Result<Range<usize>, MemoryError> {
+        let start = addr
+            .checked_sub(self.base)
+            .filter(|offset| *offset >= 0)
+            .map(|offset| offset as usize)
+            .ok_or(MemoryError::OutOfBounds(addr, len))?;
+        match start.checked_add(len) {
+            Some(end) if end <= self.data.len() => Ok(start..end),
+            _ => Err(MemoryError::OutOfBounds(addr, len)),
+        }
+    }
+
+    pub fn read(&self, addr: i64, len: usize) -> Result<&[u8], MemoryError> {
+        let range = self.range(addr, len)?;
+        Ok(&self.data[range])
+    }
+
+    /// Formats `len` bytes starting at `addr` as a hex dump, 16 bytes per line
+    pub fn dump(&self, addr: i64, len: usize) -> Result<String, MemoryError> {
+        let mut dump = String::new();
+        for (row, bytes) in self.read(addr, len)?.chunks(16).enumerate() {
+            dump.push_str(&format!("{:#x}:", addr + 16 * row as i64));
+            for byte in bytes {
+                dump.push_str(&format!(" {:02x}", byte));
+            }
+            dump.push('\n');
+        }
+        Ok(dump)
+    }
+
+    /// Parses an address written as a decimal or `0x` hex number, or as
+    /// `mem` optionally followed by `+offset`
+    pub fn parse_address(&self, arg: &str) -> Option<i64> {
+        let (base, offset) = match arg.strip_prefix("mem") {
+            Some("") => return Some(self.base),
+            Some(rest) => (self.base, rest.strip_prefix('+')?),
+            None => (0, arg),
+        };
+        let offset = match offset.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => offset.parse::<i64>(),
+        };
+        offset.ok().and_then(|o| base.checked_add(o))
+    }
+
+    /// Loads 8 bytes at `addr`, little-endian
+    pub fn load(&self, addr: i64) -> Result<i64, MemoryError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.read(addr, 8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    /// Stores all 8 bytes of `value` at `addr`, little-endian
+    pub fn store(&mut self, addr: i64, value: i64) -> Result<(), MemoryError> {
+        let range = self.range(addr, 8)?;
+        self.write(range, &value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Loads `width` bytes at `addr`, extending them to 64 bits according to
+    /// the width's signedness
+    pub fn load_sized(&self, addr: i64, width: Width) -> Result<i64, MemoryError> {
+        let mut bytes = [0u8; 8];
+        bytes[..width.bytes()].copy_from_slice(self.read(addr, width.bytes())?);
+        let value = i64::from_le_bytes(bytes);
+        let shift = 64 - 8 * width.bytes() as u32;
+        if width.signed() {
+            Ok((value << shift) >> shift)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Stores the lowest `width` bytes of `value` at `addr`
+    pub fn store_sized(&mut self, addr: i64, value: i64, width: Width) -> Result<(), MemoryError> {
+        let range = self.range(addr, width.bytes())?;
+        self.write(range, &value.to_le_bytes()[..width.bytes()]);
+        Ok(())
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
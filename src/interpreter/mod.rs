@@ -0,0 +1,586 @@
+pub mod memory;
+pub mod program;
+pub mod trace;
+
+use std::{collections::HashMap, io::Write, rc::Rc};
+
+use crate::{
+    lexer::tokens::{TargetType, Token, TokenType},
+    lib::{
+        errors::{LocatedError, ParsingError, RuntimeError},
+        utils::{LocatedResult, Location},
+    },
+};
+
+use self::{
+    memory::Memory,
+    program::{parse, pushes, Block, Extern, Function, Test},
+    trace::Tracer,
+};
+
+/// Number of simulated registers, `r1` to `r8`. They are global: calls
+/// neither save nor restore them, so a function sees and changes the values
+/// of its caller
+pub const REGISTERS: usize = 8;
+
+const BUILTINS: [&str; 7] = ["dup", "drop", "swap", "over", "rot", "assert", "assert_eq"];
+
+struct Frame {
+    block: Rc<Block>,
+    ip: usize,
+    base: usize,
+    locals: Vec<(String, i64)>,
+    function: Option<Rc<Function>>,
+    call_loc: Option<Location>,
+}
+
+/// State `eval_atomic` restores when evaluation fails
+struct Checkpoint {
+    stack: Vec<i64>,
+    registers: [i64; REGISTERS],
+    functions: HashMap<String, Rc<Function>>,
+    tests: Vec<Test>,
+}
+
+enum Flow {
+    Next,
+    Jump(usize),
+    Call(Rc<Function>),
+}
+
+/// Executes LCL programs token by token, mirroring the semantics of the
+/// compiled binary: every non-inline function call gets its own stack frame,
+/// arguments are moved into locals and a returning function hands its last
+/// pushed value back to the caller.
+pub struct Interpreter<W: Write> {
+    stack: Vec<i64>,
+    memory: Memory,
+    registers: [i64; REGISTERS],
+    functions: HashMap<String, Rc<Function>>,
+    tests: Vec<Test>,
+    frames: Vec<Frame>,
+    output: W,
+    tracer: Option<Tracer>,
+}
+
+impl<W: Write> Interpreter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            stack: Vec::new(),
+            memory: Memory::new(),
+            registers: [0; REGISTERS],
+            functions: HashMap::new(),
+            tests: Vec::new(),
+            frames: Vec::new(),
+            output,
+            tracer: None,
+        }
+    }
+
+    /// Logs every token executed from now on with `tracer`
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Forgets the stack, memory, registers and functions, keeping the output
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.memory = Memory::new();
+        self.registers = [0; REGISTERS];
+        self.functions.clear();
+        self.tests.clear();
+        self.frames.clear();
+    }
+
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn registers(&self) -> &[i64; REGISTERS] {
+        &self.registers
+    }
+
+    pub fn functions(&self) -> &HashMap<String, Rc<Function>> {
+        &self.functions
+    }
+
+    /// Test blocks of every loaded program
+    pub fn tests(&self) -> &[Test] {
+        &self.tests
+    }
+
+    /// Token that the next `step` executes
+    pub fn next_token(&self) -> Option<&Token> {
+        let frame = self.frames.last()?;
+        frame.block.tokens.get(frame.ip)
+    }
+
+    /// Token preceding `next_token` in the current block
+    pub fn previous_token(&self) -> Option<&Token> {
+        let frame = self.frames.last()?;
+        frame.block.tokens.get(frame.ip.checked_sub(1)?)
+    }
+
+    /// Number of active frames, including the top-level one
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn locals(&self) -> &[(String, i64)] {
+        self.frames.last().map_or(&[], |f| f.locals.as_slice())
+    }
+
+    /// Active frames from the innermost one, with the name of their function
+    /// and the location they are executing
+    pub fn backtrace(&self) -> Vec<(String, Location)> {
+        let mut trace = Vec::new();
+        let mut loc = self.next_token().map(|t| t.loc.clone());
+        for frame in self.frames.iter().rev() {
+            let name = match &frame.function {
+                Some(f) if f.inline => format!("{} (inline)", f.name),
+                Some(f) => f.name.clone(),
+                None => "<main>".to_string(),
+            };
+            trace.push((name, loc.unwrap_or_default()));
+            loc = frame.call_loc.clone();
+        }
+        trace
+    }
+
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Defines functions of `program` and prepares its top-level code for
+    /// execution, without running anything yet
+    pub fn load(&mut self, program: Vec<Token>) -> LocatedResult<()> {
+        let program = parse(program)?;
+
+        let mut functions = self.functions.clone();
+        for mut function in program.functions {
+            function.returning = function.body.tokens.last().is_some_and(|t| {
+                pushes(t, &function.params, |name| {
+                    functions.get(name).is_some_and(|f| f.returning)
+                })
+            });
+            functions.insert(function.name.clone(), Rc::new(function));
+        }
+
+        let externs = &program.externs;
+        for function in functions.values() {
+            resolve(&function.body, &function.params, &functions, externs)?;
+        }
+        for test in program.tests.iter() {
+            resolve(&test.body, &[], &functions, externs)?;
+        }
+        resolve(&program.main, &[], &functions, externs)?;
+
+        self.functions = functions;
+        self.tests.extend(program.tests);
+        if !program.main.is_empty() {
+            self.frames.push(Frame {
+                block: Rc::new(program.main),
+                ip: 0,
+                base: 0,
+                locals: Vec::new(),
+                function: None,
+                call_loc: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Loads `program` and runs it to completion
+    pub fn eval(&mut self, program: Vec<Token>) -> LocatedResult<()> {
+        self.load(program)?;
+        self.run()
+    }
+
+    /// Like `eval`, but if `program` fails the stack, memory, registers and
+    /// functions are rolled back to their state before it
+    pub fn eval_atomic(&mut self, program: Vec<Token>) -> LocatedResult<()> {
+        let checkpoint = Checkpoint {
+            stack: self.stack.clone(),
+            registers: self.registers,
+            functions: self.functions.clone(),
+            tests: self.tests.clone(),
+        };
+        self.memory.begin();
+
+        match self.eval(program) {
+            Ok(()) => {
+                self.memory.commit();
+                Ok(())
+            }
+            Err(e) => {
+                self.memory.rollback();
+                self.stack = checkpoint.stack;
+                self.registers = checkpoint.registers;
+                self.functions = checkpoint.functions;
+                self.tests = checkpoint.tests;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> LocatedResult<()> {
+        let mut result = Ok(());
+        while self.is_running() && result.is_ok() {
+            result = self.step();
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer
+                .flush()
+                .map_err(|e| LocatedError::new(Location::default(), RuntimeError::Output(e)))?;
+        }
+        result
+    }
+
+    /// Runs `test` on a fresh stack, memory and registers, abandoning
+    /// whatever was being executed
+    pub fn run_test(&mut self, test: &Test) -> LocatedResult<()> {
+        self.stack.clear();
+        self.memory = Memory::new();
+        self.registers = [0; REGISTERS];
+        self.frames.clear();
+        if !test.body.is_empty() {
+            self.frames.push(Frame {
+                block: test.body.clone(),
+                ip: 0,
+                base: 0,
+                locals: Vec::new(),
+                function: None,
+                call_loc: None,
+            });
+        }
+        self.run()
+    }
+
+    /// Executes the next token, returning from every function it finishes.
+    /// Execution is abandoned if the token fails.
+    pub fn step(&mut self) -> LocatedResult<()> {
+        let result = self.try_step();
+        if result.is_err() {
+            self.frames.clear();
+        }
+        result
+    }
+
+    fn try_step(&mut self) -> LocatedResult<()> {
+        let frame = self.frames.last().expect("nothing to execute");
+        let block = frame.block.clone();
+        let ip = frame.ip;
+        let token = &block.tokens[ip];
+        let traced = self.tracer.as_ref().is_some_and(|t| {
+            t.traces(
+                self.frames
+                    .iter()
+                    .filter_map(|f| f.function.as_ref().map(|f| f.name.as_str())),
+            )
+        });
+
+        let flow = self
+            .execute(token, ip, &block)
+            .map_err(|e| LocatedError::new(token.loc.clone(), e))?;
+
+        match flow {
+            Flow::Next => self.frames.last_mut().unwrap().ip += 1,
+            Flow::Jump(target) => self.frames.last_mut().unwrap().ip = target,
+            Flow::Call(function) => {
+                self.frames.last_mut().unwrap().ip += 1;
+                self.call(function, &token.loc)
+                    .map_err(|e| LocatedError::new(token.loc.clone(), e))?;
+            }
+        }
+
+        self.finish_frames()?;
+        if traced {
+            let tracer = self.tracer.as_mut().unwrap();
+            tracer
+                .log(&token.loc, &token.ttype.to_string(), &self.stack)
+                .map_err(|e| LocatedError::new(token.loc.clone(), RuntimeError::Output(e)))?;
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, function: Rc<Function>, loc: &Location) -> Result<(), RuntimeError> {
+        let mut locals = Vec::with_capacity(function.params.len());
+        for param in function.params.iter() {
+            locals.push((param.clone(), self.pop()?));
+        }
+        let base = if function.inline {
+            self.frames.last().unwrap().base
+        } else {
+            self.stack.len()
+        };
+
+        self.frames.push(Frame {
+            block: function.body.clone(),
+            ip: 0,
+            base,
+            locals,
+            function: Some(function),
+            call_loc: Some(loc.clone()),
+        });
+        Ok(())
+    }
+
+    fn finish_frames(&mut self) -> LocatedResult<()> {
+        while let Some(frame) = self.frames.last() {
+            if frame.ip < frame.block.len() {
+                break;
+            }
+
+            let frame = self.frames.pop().unwrap();
+            if let Some(function) = frame.function.filter(|f| !f.inline) {
+                let value = if function.returning {
+                    if self.stack.len() <= frame.base {
+                        return Err(LocatedError::new(
+                            function.loc.clone(),
+                            RuntimeError::EmptyStack,
+                        ));
+                    }
+                    self.stack.pop()
+                } else {
+                    None
+                };
+                self.stack.truncate(frame.base);
+                self.stack.extend(value);
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i64, RuntimeError> {
+        let base = self.frames.last().map_or(0, |f| f.base);
+        if self.stack.len() > base {
+            Ok(self.stack.pop().unwrap())
+        } else {
+            Err(RuntimeError::EmptyStack)
+        }
+    }
+
+    fn push(&mut self, v: i64) {
+        self.stack.push(v);
+    }
+
+    fn register(&mut self, idx: usize) -> Result<&mut i64, RuntimeError> {
+        if (1..=REGISTERS).contains(&idx) {
+            Ok(&mut self.registers[idx - 1])
+        } else {
+            Err(RuntimeError::RegisterIndex(idx))
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<i64> {
+        let frame = self.frames.last()?;
+        frame
+            .locals
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+
+    fn execute(&mut self, token: &Token, ip: usize, block: &Block) -> Result<Flow, RuntimeError> {
+        match &token.ttype {
+            TokenType::Plus => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(a.wrapping_add(b));
+            }
+            TokenType::Minus => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push(b.wrapping_sub(a));
+            }
+            TokenType::Dot => {
+                let a = self.pop()?;
+                writeln!(self.output, "{}", a)?;
+            }
+            TokenType::Less => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push((b < a) as i64);
+            }
+            TokenType::Greater => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push((b > a) as i64);
+            }
+            TokenType::Equal => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push((a == b) as i64);
+            }
+            TokenType::NotEqual => {
+                let a = self.pop()?;
+                let b = self.pop()?;
+                self.push((a != b) as i64);
+            }
+            TokenType::If | TokenType::Do => {
+                if self.pop()? == 0 {
+                    return Ok(Flow::Jump(block.jump(ip).unwrap()));
+                }
+            }
+            TokenType::Else => return Ok(Flow::Jump(block.jump(ip).unwrap())),
+            TokenType::End => {
+                if let Some(target) = block.jump(ip) {
+                    return Ok(Flow::Jump(target));
+                }
+            }
+            TokenType::While => {}
+            TokenType::Identifier(ident) => match ident.as_str() {
+                "dup" => {
+                    let a = self.pop()?;
+                    self.push(a);
+                    self.push(a);
+                }
+                "drop" => {
+                    self.pop()?;
+                }
+                "swap" => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.push(a);
+                    self.push(b);
+                }
+                "over" => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.push(b);
+                    self.push(a);
+                    self.push(b);
+                }
+                "rot" => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    let c = self.pop()?;
+                    self.push(b);
+                    self.push(a);
+                    self.push(c);
+                }
+                "assert" => {
+                    if self.pop()? == 0 {
+                        return Err(RuntimeError::Assert);
+                    }
+                }
+                "assert_eq" => {
+                    let expected = self.pop()?;
+                    let got = self.pop()?;
+                    if expected != got {
+                        return Err(RuntimeError::AssertEq(expected, got));
+                    }
+                }
+                name => {
+                    if let Some(v) = self.local(name) {
+                        self.push(v);
+                    } else {
+                        let function = self.functions[name].clone();
+                        return Ok(Flow::Call(function));
+                    }
+                }
+            },
+            TokenType::Mem => self.push(self.memory.base()),
+            TokenType::Push(target) => match target {
+                TargetType::Integer(v) => self.push(*v),
+                TargetType::Regsiter(i) => {
+                    let v = *self.register(*i)?;
+                    self.push(v);
+                }
+                TargetType::Memory => {
+                    let a = self.pop()?;
+                    let b = self.memory.load(a)?;
+                    self.push(b);
+                }
+                TargetType::SizedMemory(width) => {
+                    let a = self.pop()?;
+                    let b = self.memory.load_sized(a, *width)?;
+                    self.push(b);
+                }
+            },
+            TokenType::Pop(target) => match target {
+                TargetType::Integer(_) => unreachable!("the lexer never produces it"),
+                TargetType::Regsiter(i) => {
+                    let a = self.pop()?;
+                    *self.register(*i)? = a;
+                }
+                TargetType::Memory => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.memory.store(b, a)?;
+                }
+                TargetType::SizedMemory(width) => {
+                    let a = self.pop()?;
+                    let b = self.pop()?;
+                    self.memory.store_sized(b, a, *width)?;
+                }
+            },
+            TokenType::Function
+            | TokenType::Inline
+            | TokenType::NoInline
+            | TokenType::Extern
+            | TokenType::Export
+            | TokenType::Returns
+            | TokenType::Str(_) => unreachable!("definitions are parsed out"),
+            TokenType::Multiply | TokenType::Divide | TokenType::Mod => {
+                unreachable!("unsupported operators are rejected when resolving")
+            }
+        }
+        Ok(Flow::Next)
+    }
+}
+
+/// Formats `stack` as its depth followed by its values, bottom first
+pub fn format_stack(stack: &[i64]) -> String {
+    let mut res = format!("<{}>", stack.len());
+    for v in stack {
+        res.push_str(&format!(" {}", v));
+    }
+    res
+}
+
+/// Checks that every identifier in `block` names a builtin, a parameter or
+/// a defined function. Extern functions only exist in compiled code, so
+/// calling one is an error as well, and so is using `*`, `/` or `%`
+fn resolve(
+    block: &Block,
+    params: &[String],
+    functions: &HashMap<String, Rc<Function>>,
+    externs: &[Extern],
+) -> LocatedResult<()> {
+    for token in block.tokens.iter() {
+        let unsupported = match token.ttype {
+            TokenType::Multiply => Some("*"),
+            TokenType::Divide => Some("/"),
+            TokenType::Mod => Some("%"),
+            _ => None,
+        };
+        if let Some(op) = unsupported {
+            let error = ParsingError::UnsupportedOperator(op.to_string());
+            return Err(LocatedError::new(token.loc.clone(), error));
+        }
+        if let TokenType::Identifier(ident) = &token.ttype {
+            let name = ident.as_str();
+            if !BUILTINS.contains(&name)
+                && !params.iter().any(|p| p == name)
+                && !functions.contains_key(name)
+            {
+                let error = if externs.iter().any(|e| e.name == name) {
+                    ParsingError::ExternCall(ident.clone())
+                } else {
+                    ParsingError::NotDefined(ident.clone())
+                };
+                return Err(LocatedError::new(token.loc.clone(), error));
+            }
+        }
+    }
+    Ok(())
+}
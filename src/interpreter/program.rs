@@ -0,0 +1,421 @@
+use std::{iter::Peekable, rc::Rc, vec::IntoIter};
+
+use crate::{
+    lexer::tokens::{Token, TokenType},
+    lib::{
+        constants::EXTERN_PARAMS,
+        errors::{LocatedError, ParsingError},
+        utils::{LocatedResult, Location},
+    },
+};
+
+/// Straight-line sequence of tokens with resolved control flow.
+///
+/// `jumps` holds, for every `if`, `else`, `do` and loop `end`, the index
+/// execution continues from when the branch is taken.
+pub struct Block {
+    pub tokens: Vec<Token>,
+    jumps: Vec<Option<usize>>,
+}
+
+impl Block {
+    fn new(tokens: Vec<Token>) -> LocatedResult<Self> {
+        let mut jumps = vec![None; tokens.len()];
+        let mut markers: Vec<usize> = Vec::new();
+
+        for (idx, token) in tokens.iter().enumerate() {
+            match token.ttype {
+                TokenType::If | TokenType::While => markers.push(idx),
+                TokenType::Else => match markers.pop() {
+                    Some(m) if tokens[m].ttype == TokenType::If => {
+                        jumps[m] = Some(idx + 1);
+                        markers.push(idx);
+                    }
+                    _ => return Err(unexpected(token)),
+                },
+                TokenType::Do => match markers.pop() {
+                    Some(m) if tokens[m].ttype == TokenType::While => {
+                        // Remember where the loop starts until its `end` is found
+                        jumps[idx] = Some(m);
+                        markers.push(idx);
+                    }
+                    _ => return Err(unexpected(token)),
+                },
+                TokenType::End => match markers.pop() {
+                    Some(m) if tokens[m].ttype == TokenType::Do => {
+                        jumps[idx] = jumps[m];
+                        jumps[m] = Some(idx + 1);
+                    }
+                    Some(m) if tokens[m].ttype != TokenType::While => {
+                        jumps[m] = Some(idx + 1);
+                    }
+                    _ => return Err(unexpected(token)),
+                },
+                TokenType::Function
+                | TokenType::Inline
+                | TokenType::NoInline
+                | TokenType::Extern
+                | TokenType::Export
+                | TokenType::Returns
+                | TokenType::Str(_) => return Err(unexpected(token)),
+                _ => {}
+            }
+        }
+
+        if let Some(m) = markers.last() {
+            return Err(LocatedError::new(
+                tokens[*m].loc.clone(),
+                ParsingError::NotEnclosedBlock,
+            ));
+        }
+
+        Ok(Self { tokens, jumps })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn jump(&self, idx: usize) -> Option<usize> {
+        self.jumps[idx]
+    }
+}
+
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Rc<Block>,
+    pub inline: bool,
+    /// Declared with `noinline fn`, the optimizer never inlines it
+    pub noinline: bool,
+    /// Declared with `export fn`, object files let C code call it
+    pub export: bool,
+    /// Whether the body ends by pushing a value, non-inline functions hand
+    /// that value back to the caller
+    pub returning: bool,
+    pub loc: Location,
+}
+
+/// `extern fn name params -- result` declaration of a function defined
+/// outside the program, called with the C calling convention
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Whether a result follows the `--`, it is pushed after the call
+    pub returning: bool,
+}
+
+/// `test "name" do ... end` block, run by `lcl test` only
+#[derive(Clone)]
+pub struct Test {
+    pub name: String,
+    pub body: Rc<Block>,
+    pub loc: Location,
+}
+
+/// Function definitions, extern declarations, tests and top-level code of a
+/// parsed program
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub externs: Vec<Extern>,
+    pub tests: Vec<Test>,
+    pub main: Block,
+}
+
+fn unexpected(token: &Token) -> LocatedError {
+    LocatedError::new(
+        token.loc.clone(),
+        ParsingError::UnexpectedToken(token.ttype.to_string()),
+    )
+}
+
+/// Collects tokens up to the `end` closing a definition, `None` if it is
+/// never closed
+fn parse_body(tokens: &mut Peekable<IntoIter<Token>>) -> Option<Vec<Token>> {
+    let mut body = Vec::new();
+    let mut depth = 0;
+    loop {
+        let t = tokens.next()?;
+        match t.ttype {
+            TokenType::End if depth == 0 => return Some(body),
+            TokenType::If | TokenType::While => depth += 1,
+            TokenType::End => depth -= 1,
+            _ => {}
+        }
+        body.push(t);
+    }
+}
+
+fn parse_function(tokens: &mut Peekable<IntoIter<Token>>, start: Token) -> LocatedResult<Function> {
+    // `inline fn name` is the same as `inline name`
+    if start.ttype == TokenType::Inline {
+        tokens.next_if(|t| t.ttype == TokenType::Function);
+    }
+    let name = match tokens.next() {
+        Some(Token {
+            ttype: TokenType::Identifier(name),
+            ..
+        }) => name,
+        Some(t) => return Err(LocatedError::new(t.loc, ParsingError::FunctionName)),
+        None => {
+            return Err(LocatedError::new(
+                start.loc,
+                ParsingError::NotEnclosedFunction,
+            ))
+        }
+    };
+
+    let mut params = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(Token {
+                ttype: TokenType::Identifier(param),
+                ..
+            }) => params.push(param),
+            Some(Token {
+                ttype: TokenType::Do,
+                ..
+            }) => break,
+            Some(t) => return Err(unexpected(&t)),
+            None => {
+                return Err(LocatedError::new(
+                    start.loc,
+                    ParsingError::NotEnclosedFunction,
+                ))
+            }
+        }
+    }
+
+    let body = parse_body(tokens)
+        .ok_or_else(|| LocatedError::new(start.loc.clone(), ParsingError::NotEnclosedFunction))?;
+
+    Ok(Function {
+        name,
+        params,
+        body: Rc::new(Block::new(body)?),
+        inline: start.ttype == TokenType::Inline,
+        noinline: false,
+        export: false,
+        returning: false,
+        loc: start.loc,
+    })
+}
+
+/// Parses the signature of an extern function, which has no body. The
+/// result is named on the same line as the `--`, so the code after a
+/// declaration without one isn't taken for it
+fn parse_extern(tokens: &mut Peekable<IntoIter<Token>>, start: Token) -> LocatedResult<Extern> {
+    let name = match tokens.next() {
+        Some(Token {
+            ttype: TokenType::Identifier(name),
+            ..
+        }) => name,
+        Some(t) => return Err(LocatedError::new(t.loc, ParsingError::FunctionName)),
+        None => return Err(LocatedError::new(start.loc, ParsingError::FunctionName)),
+    };
+
+    let mut params = Vec::new();
+    let dashes = loop {
+        match tokens.next() {
+            Some(Token {
+                ttype: TokenType::Identifier(param),
+                ..
+            }) => params.push(param),
+            Some(t) if t.ttype == TokenType::Returns => break t,
+            Some(t) => {
+                return Err(LocatedError::new(
+                    t.loc,
+                    ParsingError::ExternSignature(name),
+                ))
+            }
+            None => {
+                return Err(LocatedError::new(
+                    start.loc,
+                    ParsingError::ExternSignature(name),
+                ))
+            }
+        }
+    };
+    if params.len() > EXTERN_PARAMS {
+        return Err(LocatedError::new(
+            start.loc,
+            ParsingError::ExternParams(name, params.len()),
+        ));
+    }
+
+    let on_line = |t: &Token| t.loc.row == dashes.loc.row;
+    let returning = match tokens.next_if(on_line) {
+        Some(Token {
+            ttype: TokenType::Identifier(_),
+            ..
+        }) => true,
+        Some(t) => return Err(unexpected(&t)),
+        None => false,
+    };
+    if let Some(t) = tokens.next_if(on_line) {
+        return Err(LocatedError::new(
+            t.loc,
+            ParsingError::ExternSignature(name),
+        ));
+    }
+
+    Ok(Extern {
+        name,
+        params,
+        returning,
+    })
+}
+
+fn parse_test(tokens: &mut Peekable<IntoIter<Token>>, start: Token) -> LocatedResult<Test> {
+    let name = match tokens.next() {
+        Some(Token {
+            ttype: TokenType::Str(name),
+            ..
+        }) => name,
+        _ => return Err(LocatedError::new(start.loc, ParsingError::TestName)),
+    };
+
+    match tokens.next() {
+        Some(Token {
+            ttype: TokenType::Do,
+            ..
+        }) => {}
+        Some(t) => return Err(unexpected(&t)),
+        None => return Err(LocatedError::new(start.loc, ParsingError::NotEnclosedTest)),
+    }
+
+    let body = parse_body(tokens)
+        .ok_or_else(|| LocatedError::new(start.loc.clone(), ParsingError::NotEnclosedTest))?;
+
+    Ok(Test {
+        name,
+        body: Rc::new(Block::new(body)?),
+        loc: start.loc,
+    })
+}
+
+/// Whether `token` followed by `next` opens a test block. `test` is not a
+/// keyword, so it can still name functions
+pub fn opens_test(token: &Token, next: Option<&Token>) -> bool {
+    matches!(&token.ttype, TokenType::Identifier(ident) if ident == "test")
+        && matches!(
+            next,
+            Some(Token {
+                ttype: TokenType::Str(_),
+                ..
+            })
+        )
+}
+
+/// Splits `tokens` into function definitions, extern declarations, tests
+/// and top-level code
+pub fn parse(tokens: Vec<Token>) -> LocatedResult<Program> {
+    let mut functions = Vec::new();
+    let mut externs = Vec::new();
+    let mut tests = Vec::new();
+    let mut main = Vec::new();
+
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token.ttype {
+            TokenType::Function | TokenType::Inline => {
+                functions.push(parse_function(&mut tokens, token)?)
+            }
+            TokenType::NoInline => match tokens.next() {
+                Some(start) if start.ttype == TokenType::Function => {
+                    let mut function = parse_function(&mut tokens, start)?;
+                    function.noinline = true;
+                    functions.push(function);
+                }
+                Some(t) => return Err(unexpected(&t)),
+                None => {
+                    return Err(LocatedError::new(
+                        token.loc,
+                        ParsingError::NotEnclosedFunction,
+                    ))
+                }
+            },
+            TokenType::Export => match tokens.next() {
+                Some(start) if start.ttype == TokenType::Function => {
+                    let mut function = parse_function(&mut tokens, start)?;
+                    function.export = true;
+                    functions.push(function);
+                }
+                Some(t) => return Err(unexpected(&t)),
+                None => {
+                    return Err(LocatedError::new(
+                        token.loc,
+                        ParsingError::NotEnclosedFunction,
+                    ))
+                }
+            },
+            TokenType::Extern => match tokens.next() {
+                Some(start) if start.ttype == TokenType::Function => {
+                    externs.push(parse_extern(&mut tokens, start)?)
+                }
+                Some(t) => return Err(unexpected(&t)),
+                None => return Err(LocatedError::new(token.loc, ParsingError::FunctionName)),
+            },
+            _ if opens_test(&token, tokens.peek()) => tests.push(parse_test(&mut tokens, token)?),
+            _ => main.push(token),
+        }
+    }
+
+    Ok(Program {
+        functions,
+        externs,
+        tests,
+        main: Block::new(main)?,
+    })
+}
+
+/// Whether executing `token` as the last one of a body leaves a freshly
+/// pushed value on top of the stack, `returning` tells whether calling a
+/// function does
+pub fn pushes(token: &Token, params: &[String], returning: impl Fn(&str) -> bool) -> bool {
+    match &token.ttype {
+        TokenType::Plus
+        | TokenType::Minus
+        | TokenType::Multiply
+        | TokenType::Divide
+        | TokenType::Mod
+        | TokenType::Less
+        | TokenType::Greater
+        | TokenType::Equal
+        | TokenType::NotEqual
+        | TokenType::Mem
+        | TokenType::Push(_) => true,
+        TokenType::Identifier(ident) => match ident.as_str() {
+            "drop" | "assert" | "assert_eq" => false,
+            "dup" | "swap" | "over" | "rot" => true,
+            name if params.iter().any(|p| p == name) => true,
+            name => returning(name),
+        },
+        _ => false,
+    }
+}
+
+/// Returns how deep `tokens` are nested in unfinished blocks and definitions
+pub fn nesting(tokens: &[Token]) -> isize {
+    tokens
+        .iter()
+        .enumerate()
+        .fold(0, |depth, (idx, t)| match t.ttype {
+            // Extern declarations have no body, and `inline fn` opens one
+            // definition
+            TokenType::Function
+                if idx > 0
+                    && matches!(tokens[idx - 1].ttype, TokenType::Extern | TokenType::Inline) =>
+            {
+                depth
+            }
+            TokenType::If | TokenType::While | TokenType::Function | TokenType::Inline => depth + 1,
+            TokenType::End => depth - 1,
+            _ if opens_test(t, tokens.get(idx + 1)) => depth + 1,
+            _ => depth,
+        })
+}
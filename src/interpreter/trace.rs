@@ -0,0 +1,42 @@
+use std::io::{Result, Write};
+
+use crate::lib::utils::Location;
+
+/// Number of values from the top of the stack printed with every operation
+const TRACE_DEPTH: usize = 4;
+
+/// Logs executed tokens, one line per token: its location, the operation
+/// and the top of the stack after it
+pub struct Tracer {
+    output: Box<dyn Write>,
+    /// Only tokens executed while one of these functions is active are
+    /// logged, everything is logged if empty
+    functions: Vec<String>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>, functions: Vec<String>) -> Self {
+        Self { output, functions }
+    }
+
+    /// Whether tokens are logged inside the call chain `active`
+    pub fn traces<'a>(&self, mut active: impl Iterator<Item = &'a str>) -> bool {
+        self.functions.is_empty() || active.any(|name| self.functions.iter().any(|f| f == name))
+    }
+
+    pub fn log(&mut self, loc: &Location, operation: &str, stack: &[i64]) -> Result<()> {
+        let top = &stack[stack.len().saturating_sub(TRACE_DEPTH)..];
+        write!(self.output, "{}\t{}\t<{}>", loc, operation, stack.len())?;
+        if top.len() < stack.len() {
+            write!(self.output, " ...")?;
+        }
+        for v in top {
+            write!(self.output, " {}", v)?;
+        }
+        writeln!(self.output)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.output.flush()
+    }
+}
@@ -0,0 +1,93 @@
+use super::{pass::Pass, BinOp, Module, Op, StackOp};
+
+/// Evaluates arithmetic, comparisons and stack manipulation on constants at
+/// compile time, and resolves branches on constant conditions
+pub struct ConstantFold;
+
+impl Pass for ConstantFold {
+    fn run(&mut self, module: &mut Module) {
+        for function in module.functions.iter_mut() {
+            function.body = fold(&function.body);
+        }
+        module.main = fold(&module.main);
+    }
+}
+
+/// Value of `a op b`, where `b` is the top of the stack
+fn eval(op: BinOp, a: i64, b: i64) -> i64 {
+    match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Less => (a < b) as i64,
+        BinOp::Greater => (a > b) as i64,
+        BinOp::Equal => (a == b) as i64,
+        BinOp::NotEqual => (a != b) as i64,
+    }
+}
+
+/// Number of constants pushed right before the end of `ops`
+fn constants(ops: &[Op]) -> usize {
+    ops.iter()
+        .rev()
+        .take_while(|op| matches!(op, Op::PushConst(_)))
+        .count()
+}
+
+fn constant(op: &Op) -> i64 {
+    match op {
+        Op::PushConst(v) => *v,
+        _ => unreachable!("checked by `constants`"),
+    }
+}
+
+fn fold(body: &[Op]) -> Vec<Op> {
+    // Constants on top of `res` are folded into every following op that
+    // only consumes them
+    let mut res: Vec<Op> = Vec::with_capacity(body.len());
+    for op in body {
+        let available = constants(&res);
+        let top = res.len();
+        match op {
+            Op::BinOp(bin) if available >= 2 => {
+                let (a, b) = (constant(&res[top - 2]), constant(&res[top - 1]));
+                res.truncate(top - 2);
+                res.push(Op::PushConst(eval(*bin, a, b)));
+                continue;
+            }
+            Op::Stack(StackOp::Dup) if available >= 1 => {
+                res.push(res[top - 1].clone());
+                continue;
+            }
+            Op::Stack(StackOp::Drop) if available >= 1 => {
+                res.pop();
+                continue;
+            }
+            Op::Stack(StackOp::Swap) if available >= 2 => {
+                res.swap(top - 2, top - 1);
+                continue;
+            }
+            Op::Stack(StackOp::Over) if available >= 2 => {
+                res.push(res[top - 2].clone());
+                continue;
+            }
+            Op::Pick(n) if available > *n => {
+                res.push(res[top - 1 - n].clone());
+                continue;
+            }
+            Op::Stack(StackOp::Rot) if available >= 3 => {
+                // a b c -> b c a
+                res[top - 3..].rotate_left(1);
+                continue;
+            }
+            Op::Branch(label) if available >= 1 => {
+                if res.pop() == Some(Op::PushConst(0)) {
+                    res.push(Op::Jump(*label));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        res.push(op.clone());
+    }
+    res
+}
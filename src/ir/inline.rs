@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use super::{pass::Pass, Function, Label, Module, Op, StackOp};
+
+/// Functions with more ops than this, after their own calls are inlined,
+/// keep being called
+pub const MAX_OPS: usize = 16;
+
+/// Why a function was or wasn't inlined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Inlined,
+    NoInline,
+    Recursive,
+    /// Number of ops in the body
+    TooLarge(usize),
+    /// The stack depth isn't the same on every path through the body, so
+    /// parameters can't be found relative to the top of the stack
+    UnknownDepth,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inlined => write!(f, "inlined"),
+            Self::NoInline => write!(f, "not inlined, marked noinline"),
+            Self::Recursive => write!(f, "not inlined, recursive"),
+            Self::TooLarge(ops) => write!(
+                f,
+                "not inlined, {} ops is over the limit of {}",
+                ops, MAX_OPS
+            ),
+            Self::UnknownDepth => write!(f, "not inlined, stack depth unknown"),
+        }
+    }
+}
+
+/// Replaces calls to small, non-recursive functions with their bodies.
+/// Functions are handled callees first, so a function inlines its own calls
+/// before its size is checked
+pub struct Inline;
+
+/// Body of a function that gets inlined, with the stack depth before each
+/// op counted from the arguments
+struct Inlinable {
+    body: Vec<Op>,
+    depths: Vec<usize>,
+    end: usize,
+    params: usize,
+    returning: bool,
+}
+
+impl Pass for Inline {
+    fn run(&mut self, module: &mut Module) {
+        let mut labels = module
+            .functions
+            .iter()
+            .flat_map(|f| f.body.iter())
+            .chain(module.main.iter())
+            .filter_map(|op| match op {
+                Op::Label(l) => Some(*l),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut decisions: HashMap<String, Decision> = HashMap::new();
+        for function in module.functions.iter() {
+            if function.noinline {
+                decisions.insert(function.name.clone(), Decision::NoInline);
+            } else if recursive(&module.functions, &function.name) {
+                decisions.insert(function.name.clone(), Decision::Recursive);
+            }
+        }
+
+        // Non-recursive functions only call functions deeper in the call
+        // graph, so every round decides at least one of them
+        let mut inlinable: HashMap<String, Inlinable> = HashMap::new();
+        let mut expanded = HashSet::new();
+        loop {
+            let mut progress = false;
+            for function in module.functions.iter_mut() {
+                if decisions.contains_key(&function.name)
+                    || !callees(&function.body).all(|name| decisions.contains_key(name))
+                {
+                    continue;
+                }
+                function.body = expand(&function.body, &inlinable, &mut labels);
+                expanded.insert(function.name.clone());
+                progress = true;
+
+                let decision = match frame(&function.body, function.returning) {
+                    _ if function.body.len() > MAX_OPS => Decision::TooLarge(function.body.len()),
+                    Some((depths, end)) => {
+                        inlinable.insert(
+                            function.name.clone(),
+                            Inlinable {
+                                body: function.body.clone(),
+                                depths,
+                                end,
+                                params: function.params,
+                                returning: function.returning,
+                            },
+                        );
+                        Decision::Inlined
+                    }
+                    None => Decision::UnknownDepth,
+                };
+                decisions.insert(function.name.clone(), decision);
+            }
+            if !progress {
+                break;
+            }
+        }
+
+        for function in module.functions.iter_mut() {
+            if !expanded.contains(&function.name) {
+                function.body = expand(&function.body, &inlinable, &mut labels);
+            }
+        }
+        module.main = expand(&module.main, &inlinable, &mut labels);
+
+        module.inlining = module
+            .functions
+            .iter()
+            .map(|f| (f.name.clone(), decisions[&f.name].clone()))
+            .collect();
+        module
+            .functions
+            .retain(|f| f.export || decisions[&f.name] != Decision::Inlined);
+    }
+}
+
+/// Names of the functions `body` calls
+fn callees(body: &[Op]) -> impl Iterator<Item = &str> {
+    body.iter().filter_map(|op| match op {
+        Op::Call { name, .. } | Op::TailCall { name, .. } => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Whether `name` calls itself, directly or through other functions
+fn recursive(functions: &[Function], name: &str) -> bool {
+    let bodies: HashMap<&str, &[Op]> = functions
+        .iter()
+        .map(|f| (f.name.as_str(), f.body.as_slice()))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut pending: Vec<&str> = callees(bodies[name]).collect();
+    while let Some(callee) = pending.pop() {
+        if callee == name {
+            return true;
+        }
+        if seen.insert(callee) {
+            pending.extend(bodies.get(callee).into_iter().flat_map(|b| callees(b)));
+        }
+    }
+    false
+}
+
+/// Items `op` takes from the stack and puts back, `None` for ops that leave
+/// the current frame
+fn effect(op: &Op) -> Option<(usize, usize)> {
+    Some(match op {
+        Op::PushConst(_) | Op::PushMem | Op::PushLocal(_) | Op::PushReg(_) => (0, 1),
+        Op::Pick(n) => (n + 1, n + 2),
+        Op::PopReg(_) | Op::Print | Op::Stack(StackOp::Drop) | Op::Branch(_) => (1, 0),
+        Op::BinOp(_) => (2, 1),
+        Op::Stack(StackOp::Dup) => (1, 2),
+        Op::Stack(StackOp::Swap) => (2, 2),
+        Op::Stack(StackOp::Over) => (2, 3),
+        Op::Stack(StackOp::Rot) => (3, 3),
+        Op::Load(_) => (1, 1),
+        Op::Store(_) => (2, 0),
+        Op::Call {
+            args, returning, ..
+        }
+        | Op::CallExtern {
+            args, returning, ..
+        } => (*args, *returning as usize),
+        Op::TailCall { .. } => return None,
+        Op::Label(_) | Op::Jump(_) => (0, 0),
+    })
+}
+
+/// Stack depth before every op of a function body and at its end, `None`
+/// if it differs between paths reaching a label or the body takes more
+/// items than it pushed
+fn frame(body: &[Op], returning: bool) -> Option<(Vec<usize>, usize)> {
+    let mut at_label: HashMap<Label, usize> = HashMap::new();
+    let mut depths = Vec::with_capacity(body.len());
+    // `None` right after a jump, until a label is reached
+    let mut depth = Some(0);
+    for op in body {
+        if let Op::Label(l) = op {
+            let d = match (depth, at_label.get(l)) {
+                (Some(d), Some(&known)) if d != known => return None,
+                (Some(d), _) => d,
+                (None, known) => *known?,
+            };
+            at_label.insert(*l, d);
+            depth = Some(d);
+        }
+
+        let d = depth?;
+        depths.push(d);
+        let (pops, pushes) = effect(op)?;
+        let after = d.checked_sub(pops)? + pushes;
+        if let Op::Jump(l) | Op::Branch(l) = op {
+            if *at_label.entry(*l).or_insert(after) != after {
+                return None;
+            }
+        }
+        depth = match op {
+            Op::Jump(_) => None,
+            _ => Some(after),
+        };
+    }
+
+    let end = depth?;
+    if returning && end == 0 {
+        return None;
+    }
+    Some((depths, end))
+}
+
+/// Body of an inline function taking `params` arguments, with parameters
+/// read from the arguments below the items it pushed. The arguments are
+/// dropped at the end, under the items left by the body. `None` if the
+/// depth of the body isn't known or it leaves more than two items
+pub fn bind(body: &[Op], params: usize) -> Option<Vec<Op>> {
+    let (depths, end) = frame(body, false)?;
+    let nip = match end {
+        0 => vec![Op::Stack(StackOp::Drop)],
+        1 => vec![Op::Stack(StackOp::Swap), Op::Stack(StackOp::Drop)],
+        2 => vec![Op::Stack(StackOp::Rot), Op::Stack(StackOp::Drop)],
+        _ => return None,
+    };
+
+    let mut res: Vec<Op> = body
+        .iter()
+        .zip(depths)
+        .map(|(op, depth)| match op {
+            Op::PushLocal(i) => Op::Pick(depth + i),
+            _ => op.clone(),
+        })
+        .collect();
+    for _ in 0..params {
+        res.extend(nip.iter().cloned());
+    }
+    Some(res)
+}
+
+/// `body` with every call to an inlinable function replaced by its body
+fn expand(body: &[Op], inlinable: &HashMap<String, Inlinable>, labels: &mut Label) -> Vec<Op> {
+    let mut res = Vec::with_capacity(body.len());
+    for op in body {
+        match op {
+            Op::Call { name, .. } if inlinable.contains_key(name) => {
+                inline(&inlinable[name], labels, &mut res)
+            }
+            _ => res.push(op.clone()),
+        }
+    }
+    res
+}
+
+/// Appends the body of `callee` to `res`, with fresh labels and parameters
+/// read from the arguments below the items it pushed. Arguments and
+/// leftovers are dropped at the end, keeping the result on top
+fn inline(callee: &Inlinable, labels: &mut Label, res: &mut Vec<Op>) {
+    let mut renamed: HashMap<Label, Label> = HashMap::new();
+    let mut rename = |l: &Label| {
+        *renamed.entry(*l).or_insert_with(|| {
+            *labels += 1;
+            *labels
+        })
+    };
+
+    for (op, depth) in callee.body.iter().zip(callee.depths.iter()) {
+        res.push(match op {
+            Op::PushLocal(i) => Op::Pick(depth + i),
+            Op::Label(l) => Op::Label(rename(l)),
+            Op::Jump(l) => Op::Jump(rename(l)),
+            Op::Branch(l) => Op::Branch(rename(l)),
+            _ => op.clone(),
+        });
+    }
+
+    if callee.returning {
+        for _ in 1..callee.end + callee.params {
+            res.push(Op::Stack(StackOp::Swap));
+            res.push(Op::Stack(StackOp::Drop));
+        }
+    } else {
+        for _ in 0..callee.end + callee.params {
+            res.push(Op::Stack(StackOp::Drop));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    interpreter::{
+        program::{parse, pushes, Extern, Function as Definition},
+        REGISTERS,
+    },
+    lexer::tokens::{TargetType, Token, TokenType},
+    lib::{
+        errors::{LocatedError, LocatedWarning, ParsingError},
+        utils::LocatedResult,
+    },
+};
+
+use super::{inline, reach, BinOp, Function, Label, Module, Op, StackOp};
+
+/// Open control flow block, with the labels its remaining keywords jump to
+enum Marker {
+    If(Label),
+    Else(Label),
+    While(Label),
+    Do(Label, Label),
+}
+
+struct Lowerer<'a> {
+    definitions: HashMap<&'a str, (&'a Definition, bool)>,
+    externs: HashMap<&'a str, &'a Extern>,
+    labels: Label,
+    /// Inline functions whose bodies are being expanded
+    expanding: Vec<&'a str>,
+}
+
+impl<'a> Lowerer<'a> {
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        self.labels
+    }
+
+    fn lower_block(
+        &mut self,
+        tokens: &'a [Token],
+        params: &[String],
+        ops: &mut Vec<Op>,
+    ) -> LocatedResult<()> {
+        // Blocks are validated by the parser, so keywords always match markers
+        let mut markers = Vec::new();
+        for token in tokens {
+            match &token.ttype {
+                TokenType::If => {
+                    let label = self.label();
+                    ops.push(Op::Branch(label));
+                    markers.push(Marker::If(label));
+                }
+                TokenType::Else => {
+                    let end = self.label();
+                    ops.push(Op::Jump(end));
+                    if let Some(Marker::If(label)) = markers.pop() {
+                        ops.push(Op::Label(label));
+                    }
+                    markers.push(Marker::Else(end));
+                }
+                TokenType::While => {
+                    let start = self.label();
+                    ops.push(Op::Label(start));
+                    markers.push(Marker::While(start));
+                }
+                TokenType::Do => {
+                    if let Some(Marker::While(start)) = markers.pop() {
+                        let end = self.label();
+                        ops.push(Op::Branch(end));
+                        markers.push(Marker::Do(start, end));
+                    }
+                }
+                TokenType::End => match markers.pop() {
+                    Some(Marker::If(label) | Marker::Else(label)) => ops.push(Op::Label(label)),
+                    Some(Marker::Do(start, end)) => {
+                        ops.push(Op::Jump(start));
+                        ops.push(Op::Label(end));
+                    }
+                    _ => unreachable!("blocks are validated by the parser"),
+                },
+                TokenType::Identifier(ident) => self.lower_identifier(token, ident, params, ops)?,
+                TokenType::Push(target) => ops.push(match target {
+                    TargetType::Integer(v) => Op::PushConst(*v),
+                    TargetType::Regsiter(i) => Op::PushReg(register(token, *i)?),
+                    TargetType::Memory => Op::Load(None),
+                    TargetType::SizedMemory(width) => Op::Load(Some(*width)),
+                }),
+                TokenType::Pop(target) => ops.push(match target {
+                    TargetType::Integer(_) => unreachable!("the lexer never produces it"),
+                    TargetType::Regsiter(i) => Op::PopReg(register(token, *i)?),
+                    TargetType::Memory => Op::Store(None),
+                    TargetType::SizedMemory(width) => Op::Store(Some(*width)),
+                }),
+                TokenType::Mem => ops.push(Op::PushMem),
+                TokenType::Dot => ops.push(Op::Print),
+                TokenType::Plus => ops.push(Op::BinOp(BinOp::Add)),
+                TokenType::Minus => ops.push(Op::BinOp(BinOp::Sub)),
+                TokenType::Multiply | TokenType::Divide | TokenType::Mod => {
+                    return Err(LocatedError::new(
+                        token.loc.clone(),
+                        ParsingError::UnsupportedOperator(token.ttype.to_string()),
+                    ))
+                }
+                TokenType::Less => ops.push(Op::BinOp(BinOp::Less)),
+                TokenType::Greater => ops.push(Op::BinOp(BinOp::Greater)),
+                TokenType::Equal => ops.push(Op::BinOp(BinOp::Equal)),
+                TokenType::NotEqual => ops.push(Op::BinOp(BinOp::NotEqual)),
+                TokenType::Function
+                | TokenType::Inline
+                | TokenType::NoInline
+                | TokenType::Extern
+                | TokenType::Export
+                | TokenType::Returns
+                | TokenType::Str(_) => {
+                    unreachable!("definitions are parsed out")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lower_identifier(
+        &mut self,
+        token: &Token,
+        ident: &str,
+        params: &[String],
+        ops: &mut Vec<Op>,
+    ) -> LocatedResult<()> {
+        let op = match ident {
+            "dup" => Op::Stack(StackOp::Dup),
+            "drop" => Op::Stack(StackOp::Drop),
+            "swap" => Op::Stack(StackOp::Swap),
+            "over" => Op::Stack(StackOp::Over),
+            "rot" => Op::Stack(StackOp::Rot),
+            "assert" | "assert_eq" => {
+                return Err(LocatedError::new(
+                    token.loc.clone(),
+                    ParsingError::TestOnly(ident.to_string()),
+                ))
+            }
+            name if params.iter().any(|p| p == name) => {
+                Op::PushLocal(params.iter().position(|p| p == name).unwrap())
+            }
+            name => match self.definitions.get(name) {
+                Some(&(definition, _)) if definition.inline => {
+                    if self.expanding.contains(&name) {
+                        return Err(LocatedError::new(
+                            token.loc.clone(),
+                            ParsingError::RecursiveInline(name.to_string()),
+                        ));
+                    }
+                    self.expanding.push(&definition.name);
+                    if definition.params.is_empty() {
+                        self.lower_block(&definition.body.tokens, &[], ops)?;
+                    } else {
+                        // Inline bodies have no frame, so parameters are
+                        // read from the arguments on the stack instead
+                        let mut body = Vec::new();
+                        self.lower_block(&definition.body.tokens, &definition.params, &mut body)?;
+                        let bound =
+                            inline::bind(&body, definition.params.len()).ok_or_else(|| {
+                                LocatedError::new(
+                                    token.loc.clone(),
+                                    ParsingError::InlineParams(name.to_string()),
+                                )
+                            })?;
+                        ops.extend(bound);
+                    }
+                    self.expanding.pop();
+                    return Ok(());
+                }
+                Some(&(definition, returning)) => Op::Call {
+                    name: name.to_string(),
+                    args: definition.params.len(),
+                    returning,
+                },
+                None if self.externs.contains_key(name) => Op::CallExtern {
+                    name: name.to_string(),
+                    args: self.externs[name].params.len(),
+                    returning: self.externs[name].returning,
+                },
+                None => {
+                    return Err(LocatedError::new(
+                        token.loc.clone(),
+                        ParsingError::NotDefined(name.to_string()),
+                    ))
+                }
+            },
+        };
+        ops.push(op);
+        Ok(())
+    }
+}
+
+fn register(token: &Token, idx: usize) -> LocatedResult<usize> {
+    if (1..=REGISTERS).contains(&idx) {
+        Ok(idx)
+    } else {
+        Err(LocatedError::new(
+            token.loc.clone(),
+            ParsingError::RegisterIndex(idx),
+        ))
+    }
+}
+
+/// Lowers a program into IR, along with warnings about unused definitions.
+/// Test blocks and functions unreachable from the top-level code and the
+/// exported functions are left out, the rest keep their definition order
+pub fn lower(tokens: Vec<Token>) -> LocatedResult<(Module, Vec<LocatedWarning>)> {
+    let program = parse(tokens)?;
+
+    let externs: HashMap<&str, &Extern> = program
+        .externs
+        .iter()
+        .map(|e| (e.name.as_str(), e))
+        .collect();
+
+    // Later definitions replace earlier ones with the same name
+    let mut definitions: HashMap<&str, (&Definition, bool)> = HashMap::new();
+    for definition in program.functions.iter() {
+        let returning = definition.body.tokens.last().is_some_and(|t| {
+            pushes(t, &definition.params, |name| match definitions.get(name) {
+                Some((_, r)) => *r,
+                None => externs.get(name).is_some_and(|e| e.returning),
+            })
+        });
+        definitions.insert(&definition.name, (definition, returning));
+    }
+
+    let mut lowerer = Lowerer {
+        definitions,
+        externs,
+        labels: 0,
+        expanding: Vec::new(),
+    };
+
+    let mut functions = Vec::new();
+    for (idx, definition) in program.functions.iter().enumerate() {
+        let replaced = program.functions[idx + 1..]
+            .iter()
+            .any(|f| f.name == definition.name);
+        if definition.inline || replaced {
+            continue;
+        }
+
+        let mut body = Vec::new();
+        lowerer.lower_block(&definition.body.tokens, &definition.params, &mut body)?;
+        functions.push(Function {
+            name: definition.name.clone(),
+            params: definition.params.len(),
+            body,
+            returning: lowerer.definitions[definition.name.as_str()].1,
+            noinline: definition.noinline,
+            export: definition.export,
+        });
+    }
+
+    let mut main = Vec::new();
+    lowerer.lower_block(&program.main.tokens, &[], &mut main)?;
+
+    // Unreachable functions are still lowered above to report their errors
+    let definitions = lowerer
+        .definitions
+        .iter()
+        .map(|(&name, &(definition, _))| (name, definition))
+        .collect();
+    let live = reach::reachable([program.main.tokens.as_slice()], &definitions);
+    functions.retain(|f| live.contains(f.name.as_str()));
+
+    let called: HashSet<&str> = functions
+        .iter()
+        .flat_map(|f| f.body.iter())
+        .chain(main.iter())
+        .filter_map(|op| match op {
+            Op::CallExtern { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let externs = program
+        .externs
+        .iter()
+        .filter(|e| called.contains(e.name.as_str()))
+        .map(|e| e.name.clone())
+        .collect();
+
+    let warnings = reach::unused(&program, &definitions);
+    Ok((
+        Module {
+            functions,
+            main,
+            externs,
+            inlining: Vec::new(),
+        },
+        warnings,
+    ))
+}
@@ -0,0 +1,211 @@
+//! Stack-machine intermediate representation the backends are generated from.
+//!
+//! Programs are lowered from tokens into a `Module`, transformed by passes of
+//! a `PassManager` and finally handed to an emitter.
+
+pub mod fold;
+pub mod inline;
+mod lower;
+pub mod pass;
+mod reach;
+pub mod tail;
+
+use std::fmt;
+
+use crate::lexer::tokens::Width;
+
+pub use self::{inline::Decision, lower::lower};
+
+pub type Label = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Less,
+    Greater,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackOp {
+    Dup,
+    Drop,
+    Swap,
+    Over,
+    Rot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    PushConst(i64),
+    /// Pushes the address of the memory block
+    PushMem,
+    /// Pushes the parameter with the given index, `0` is the top of the
+    /// stack at the time of the call
+    PushLocal(usize),
+    /// Pushes a copy of the item the given number of places below the top
+    /// of the stack, `0` being the top itself
+    Pick(usize),
+    PushReg(usize),
+    PopReg(usize),
+    BinOp(BinOp),
+    Stack(StackOp),
+    /// Replaces an address with the value stored there, a whole cell if no
+    /// width is given
+    Load(Option<Width>),
+    /// Pops a value and an address and stores the value there
+    Store(Option<Width>),
+    Print,
+    /// Calls a non-inline function, its arguments are removed from the stack
+    /// and its result, if any, is pushed
+    Call {
+        name: String,
+        args: usize,
+        returning: bool,
+    },
+    /// Calls a function defined outside the program with the C calling
+    /// convention, passing the arguments on top of the stack in registers
+    CallExtern {
+        name: String,
+        args: usize,
+        returning: bool,
+    },
+    /// Jumps to a function taking as many arguments as the current one,
+    /// which returns straight to the caller of the current function
+    TailCall {
+        name: String,
+        args: usize,
+    },
+    Label(Label),
+    Jump(Label),
+    /// Pops a value and jumps if it is `0`
+    Branch(Label),
+}
+
+pub struct Function {
+    pub name: String,
+    pub params: usize,
+    pub body: Vec<Op>,
+    /// Whether the function hands its last pushed value back to the caller
+    pub returning: bool,
+    /// Declared with `noinline fn`
+    pub noinline: bool,
+    /// Declared with `export fn`, kept even when every call is inlined
+    pub export: bool,
+}
+
+/// Non-inline functions and top-level code of a program, inline functions
+/// are expanded at every call
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub main: Vec<Op>,
+    /// Extern functions the code calls, in declaration order
+    pub externs: Vec<String>,
+    /// Whether each function was inlined, in definition order, empty
+    /// unless the `Inline` pass ran
+    pub inlining: Vec<(String, Decision)>,
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Less => "less",
+            Self::Greater => "greater",
+            Self::Equal => "equal",
+            Self::NotEqual => "not_equal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for StackOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Dup => "dup",
+            Self::Drop => "drop",
+            Self::Swap => "swap",
+            Self::Over => "over",
+            Self::Rot => "rot",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PushConst(v) => write!(f, "push_const {}", v),
+            Self::PushMem => write!(f, "push_mem"),
+            Self::PushLocal(i) => write!(f, "push_local {}", i),
+            Self::Pick(i) => write!(f, "pick {}", i),
+            Self::PushReg(i) => write!(f, "push_reg r{}", i),
+            Self::PopReg(i) => write!(f, "pop_reg r{}", i),
+            Self::BinOp(op) => write!(f, "bin_op {}", op),
+            Self::Stack(op) => write!(f, "{}", op),
+            Self::Load(None) => write!(f, "load"),
+            Self::Load(Some(width)) => write!(f, "load {}", width),
+            Self::Store(None) => write!(f, "store"),
+            Self::Store(Some(width)) => write!(f, "store {}", width),
+            Self::Print => write!(f, "print"),
+            Self::Call {
+                name,
+                args,
+                returning,
+            } => {
+                write!(f, "call {} args={}", name, args)?;
+                if *returning {
+                    write!(f, " returning")?;
+                }
+                Ok(())
+            }
+            Self::CallExtern {
+                name,
+                args,
+                returning,
+            } => {
+                write!(f, "call_extern {} args={}", name, args)?;
+                if *returning {
+                    write!(f, " returning")?;
+                }
+                Ok(())
+            }
+            Self::TailCall { name, args } => write!(f, "tail_call {} args={}", name, args),
+            Self::Label(l) => write!(f, "L{}:", l),
+            Self::Jump(l) => write!(f, "jump L{}", l),
+            Self::Branch(l) => write!(f, "branch L{}", l),
+        }
+    }
+}
+
+fn fmt_body(f: &mut fmt::Formatter, body: &[Op]) -> fmt::Result {
+    for op in body {
+        match op {
+            Op::Label(_) => writeln!(f, "{}", op)?,
+            _ => writeln!(f, "\t{}", op)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for function in self.functions.iter() {
+            write!(f, "fn {} params={}", function.name, function.params)?;
+            if function.returning {
+                write!(f, " returning")?;
+            }
+            if function.export {
+                write!(f, " export")?;
+            }
+            writeln!(f)?;
+            fmt_body(f, &function.body)?;
+            writeln!(f)?;
+        }
+        writeln!(f, "main")?;
+        fmt_body(f, &self.main)
+    }
+}
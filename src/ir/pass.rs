@@ -0,0 +1,29 @@
+use super::Module;
+
+/// Transformation of a whole module
+pub trait Pass {
+    fn run(&mut self, module: &mut Module);
+}
+
+/// Runs passes over a module in the order they were added
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn run(&mut self, module: &mut Module) {
+        for pass in self.passes.iter_mut() {
+            pass.run(module);
+        }
+    }
+}
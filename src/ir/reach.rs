@@ -0,0 +1,100 @@
+//! Which definitions a program uses, following references from its
+//! top-level code.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    interpreter::program::{Function as Definition, Program},
+    lexer::tokens::{Token, TokenType},
+    lib::errors::{LocatedWarning, Warning},
+};
+
+/// Definitions named in `tokens`, a body with the given params
+fn references<'a>(
+    tokens: &'a [Token],
+    params: &[String],
+    definitions: &HashMap<&str, &Definition>,
+) -> Vec<&'a str> {
+    tokens
+        .iter()
+        .filter_map(|token| match &token.ttype {
+            TokenType::Identifier(name)
+                if !params.contains(name) && definitions.contains_key(name.as_str()) =>
+            {
+                Some(name.as_str())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Names of the definitions reachable from `roots` or from exported
+/// functions, inline functions included
+pub fn reachable<'a>(
+    roots: impl IntoIterator<Item = &'a [Token]>,
+    definitions: &HashMap<&'a str, &'a Definition>,
+) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<&str> = roots
+        .into_iter()
+        .flat_map(|tokens| references(tokens, &[], definitions))
+        .chain(
+            definitions
+                .iter()
+                .filter(|(_, definition)| definition.export)
+                .map(|(&name, _)| name),
+        )
+        .collect();
+
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name) {
+            continue;
+        }
+        let definition = definitions[name];
+        pending.extend(references(
+            &definition.body.tokens,
+            &definition.params,
+            definitions,
+        ));
+    }
+    seen
+}
+
+/// Warnings for functions never used by the top-level code, tests or
+/// exported functions, and parameters never used by their function
+pub fn unused(program: &Program, definitions: &HashMap<&str, &Definition>) -> Vec<LocatedWarning> {
+    let roots = std::iter::once(program.main.tokens.as_slice())
+        .chain(program.tests.iter().map(|t| t.body.tokens.as_slice()));
+    let used = reachable(roots, definitions);
+
+    let mut warnings = Vec::new();
+    for (idx, definition) in program.functions.iter().enumerate() {
+        // Replaced definitions are never used by design
+        if program.functions[idx + 1..]
+            .iter()
+            .any(|f| f.name == definition.name)
+        {
+            continue;
+        }
+
+        if !used.contains(definition.name.as_str()) {
+            warnings.push(LocatedWarning::new(
+                definition.loc.clone(),
+                Warning::UnusedFunction(definition.name.clone()),
+            ));
+        }
+        for param in definition.params.iter() {
+            let read = definition.body.tokens.iter().any(|t| match &t.ttype {
+                TokenType::Identifier(name) => name == param,
+                _ => false,
+            });
+            if !read {
+                warnings.push(LocatedWarning::new(
+                    definition.loc.clone(),
+                    Warning::UnusedParam(param.clone(), definition.name.clone()),
+                ));
+            }
+        }
+    }
+    warnings
+}
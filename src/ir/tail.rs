@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use super::{pass::Pass, Function, Label, Module, Op};
+
+/// Turns calls in tail position into jumps reusing the frame of the caller.
+/// Only callees with as many params as the caller qualify, since the caller
+/// of the current function removes that many arguments once it returns
+pub struct TailCalls;
+
+impl Pass for TailCalls {
+    fn run(&mut self, module: &mut Module) {
+        for function in module.functions.iter_mut() {
+            rewrite(function);
+        }
+    }
+}
+
+/// Whether nothing but jumps and labels follow `idx` until the end of `body`
+fn in_tail_position(body: &[Op], labels: &HashMap<Label, usize>, idx: usize) -> bool {
+    let mut next = idx + 1;
+    // Every jump moves to a different label, so this many steps is enough
+    // to find the end or a cycle
+    for _ in 0..=body.len() {
+        match body.get(next) {
+            None => return true,
+            Some(Op::Label(_)) => next += 1,
+            Some(Op::Jump(label)) => next = labels[label],
+            Some(_) => return false,
+        }
+    }
+    false
+}
+
+fn rewrite(function: &mut Function) {
+    let labels: HashMap<Label, usize> = function
+        .body
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, op)| match op {
+            Op::Label(label) => Some((*label, idx)),
+            _ => None,
+        })
+        .collect();
+
+    for idx in 0..function.body.len() {
+        let tail = match &function.body[idx] {
+            Op::Call { name, args, .. } if *args == function.params => {
+                in_tail_position(&function.body, &labels, idx).then(|| Op::TailCall {
+                    name: name.clone(),
+                    args: *args,
+                })
+            }
+            _ => None,
+        };
+        if let Some(op) = tail {
+            function.body[idx] = op;
+        }
+    }
+}
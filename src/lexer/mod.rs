@@ -5,7 +5,7 @@ use crate::lib::{
     utils::{fetch_while, LocatedResult, Location},
 };
 
-use self::tokens::{tokenize_word, Token};
+use self::tokens::{tokenize_word, Token, TokenType};
 
 struct Lexer<'a> {
     loc: Location,
@@ -28,6 +28,8 @@ impl<'a> Lexer<'a> {
 
         if self.src.is_empty() {
             Ok(None)
+        } else if self.src.starts_with('"') {
+            self.next_string().map(Some)
         } else {
             let word = match fetch_while(self.src, |c| !c.is_whitespace()) {
                 Ok((w, _)) => w,
@@ -56,6 +58,29 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Lexes a string literal, which cannot span several lines
+    fn next_string(&mut self) -> LocatedResult<Token> {
+        let line = self.src.lines().next().unwrap();
+        let size = match line[1..].find('"') {
+            Some(end) => end + 2,
+            None => {
+                return Err(LocatedError::new(
+                    self.loc.clone(),
+                    LexingError::UnterminatedString(line.to_string()),
+                ))
+            }
+        };
+
+        let token = Token {
+            ttype: TokenType::Str(self.src[1..size - 1].to_string()),
+            loc: self.loc.clone(),
+        };
+        self.loc.idx += size;
+        self.loc.col += size;
+        self.src = &self.src[size..];
+        Ok(token)
+    }
+
     fn skip(&mut self) {
         loop {
             let (comments, newlines) = skip_comments(self.src);
@@ -70,13 +95,15 @@ impl<'a> Lexer<'a> {
     }
 
     fn jump(&mut self, len: usize, newlines: usize) {
+        let skipped = &self.src[..len];
         self.src = &self.src[len..];
         self.loc.idx += len;
-        if newlines == 0 {
-            self.loc.col += len;
-        } else {
-            self.loc.col = 1;
-            self.loc.row += newlines;
+        match skipped.rfind('\n') {
+            Some(last) if newlines > 0 => {
+                self.loc.col = len - last;
+                self.loc.row += newlines;
+            }
+            _ => self.loc.col += len,
         }
     }
 }
@@ -1,31 +1,76 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 use crate::lib::{
     errors::LexingError,
     utils::{LexingResult, Location},
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub ttype: TokenType,
     pub loc: Location,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Width {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl Width {
+    pub fn bytes(&self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 => 4,
+        }
+    }
+
+    pub fn signed(&self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32)
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::U8 => "u8",
+            Self::I8 => "i8",
+            Self::U16 => "u16",
+            Self::I16 => "i16",
+            Self::U32 => "u32",
+            Self::I32 => "i32",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TargetType {
     Integer(i64),
     Regsiter(usize),
     Memory,
+    SizedMemory(Width),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenType {
     // Integer(i64),
     Identifier(String),
+    Str(String),
     Push(TargetType),
     Pop(TargetType),
     Inline,
+    NoInline,
+    Extern,
+    Export,
     Function,
+    /// `--`, separates the parameters of an extern function from its result
+    Returns,
     Dot,
     Plus,
     Minus,
@@ -44,6 +89,52 @@ pub enum TokenType {
     End,
 }
 
+impl fmt::Display for TargetType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Regsiter(i) => write!(f, "r{}", i),
+            Self::Memory => Ok(()),
+            Self::SizedMemory(width) => write!(f, "{}", width),
+        }
+    }
+}
+
+/// Formats tokens the way they are written in source code
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Identifier(ident) => write!(f, "{}", ident),
+            Self::Str(s) => write!(f, "\"{}\"", s),
+            Self::Push(TargetType::Integer(v)) => write!(f, "{}", v),
+            Self::Push(target) => write!(f, "!{}", target),
+            Self::Pop(target) => write!(f, "@{}", target),
+            Self::Inline => write!(f, "inline"),
+            Self::NoInline => write!(f, "noinline"),
+            Self::Extern => write!(f, "extern"),
+            Self::Export => write!(f, "export"),
+            Self::Function => write!(f, "fn"),
+            Self::Returns => write!(f, "--"),
+            Self::Dot => write!(f, "."),
+            Self::Plus => write!(f, "+"),
+            Self::Minus => write!(f, "-"),
+            Self::Multiply => write!(f, "*"),
+            Self::Divide => write!(f, "/"),
+            Self::Mod => write!(f, "%"),
+            Self::Less => write!(f, "<"),
+            Self::Greater => write!(f, ">"),
+            Self::Equal => write!(f, "="),
+            Self::NotEqual => write!(f, "!="),
+            Self::Mem => write!(f, "mem"),
+            Self::If => write!(f, "if"),
+            Self::Else => write!(f, "else"),
+            Self::While => write!(f, "while"),
+            Self::Do => write!(f, "do"),
+            Self::End => write!(f, "end"),
+        }
+    }
+}
+
 impl FromStr for TokenType {
     type Err = LexingError;
 
@@ -51,6 +142,7 @@ impl FromStr for TokenType {
         match s {
             "+" => Ok(Self::Plus),
             "-" => Ok(Self::Minus),
+            "--" => Ok(Self::Returns),
             "*" => Ok(Self::Multiply),
             "/" => Ok(Self::Divide),
             "%" => Ok(Self::Mod),
@@ -67,6 +159,9 @@ impl FromStr for TokenType {
             "mem" => Ok(Self::Mem),
             "fn" => Ok(Self::Function),
             "inline" => Ok(Self::Inline),
+            "noinline" => Ok(Self::NoInline),
+            "extern" => Ok(Self::Extern),
+            "export" => Ok(Self::Export),
             other if other.starts_with('!') && other.len() == 1 => {
                 Ok(Self::Push(TargetType::Memory))
             }
@@ -80,6 +175,20 @@ impl FromStr for TokenType {
             {
                 Ok(Self::Push(tokenize_number(&other[1..])?))
             }
+            other
+                if other.starts_with('!')
+                    && other.len() > 1
+                    && (other[1..].starts_with('u') || other[1..].starts_with('i')) =>
+            {
+                Ok(Self::Push(tokenize_width(&other[1..])?))
+            }
+            other
+                if other.starts_with('@')
+                    && other.len() > 1
+                    && (other[1..].starts_with('u') || other[1..].starts_with('i')) =>
+            {
+                Ok(Self::Pop(tokenize_width(&other[1..])?))
+            }
             other if other.starts_with('!') && other.len() > 1 && other[1..].starts_with('r') => {
                 Ok(Self::Push(tokenize_register(&other[2..])?))
             }
@@ -113,6 +222,18 @@ fn tokenize_register(number: &str) -> LexingResult<TargetType> {
     }
 }
 
+fn tokenize_width(width: &str) -> LexingResult<TargetType> {
+    match width {
+        "u8" => Ok(TargetType::SizedMemory(Width::U8)),
+        "i8" => Ok(TargetType::SizedMemory(Width::I8)),
+        "u16" => Ok(TargetType::SizedMemory(Width::U16)),
+        "i16" => Ok(TargetType::SizedMemory(Width::I16)),
+        "u32" => Ok(TargetType::SizedMemory(Width::U32)),
+        "i32" => Ok(TargetType::SizedMemory(Width::I32)),
+        _ => Err(LexingError::MemoryWidth(width.to_string())),
+    }
+}
+
 fn tokenize_identifier(ident: &str) -> LexingResult<TokenType> {
     let first_char = ident.chars().next().unwrap();
     if first_char.is_numeric() || first_char == '.' {
@@ -1,3 +1,11 @@
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 pub const PKG_DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
+
+/// Size of the `mem` block in 8-byte cells
+pub const MEM_CAPACITY: usize = 262144;
+/// Address the simulated `mem` block starts at
+pub const MEM_BASE: i64 = 0x400000;
+/// Most parameters an extern function can take, the integer argument
+/// registers of the System V ABI on x86_64
+pub const EXTERN_PARAMS: usize = 6;
@@ -1,18 +1,67 @@
 use core::fmt;
 use std::error::Error;
 
-use super::utils::Location;
+use super::{constants::EXTERN_PARAMS, utils::Location};
 
 #[derive(Debug)]
 pub enum LexingError {
     RegisterIndex(String),
+    MemoryWidth(String),
     ParsingNumber(String),
     ParsingIdentifier(String),
     Syntax(String),
+    UnterminatedString(String),
     UnexpectedEOF,
     NoMatch,
 }
 
+#[derive(Debug)]
+pub enum MemoryError {
+    OutOfBounds(i64, usize),
+}
+
+#[derive(Debug)]
+pub enum ParsingError {
+    UnexpectedToken(String),
+    FunctionName,
+    TestName,
+    NotEnclosedBlock,
+    NotEnclosedFunction,
+    NotEnclosedTest,
+    NotDefined(String),
+    RegisterIndex(usize),
+    TestOnly(String),
+    RecursiveInline(String),
+    InlineParams(String),
+    ExternSignature(String),
+    ExternParams(String, usize),
+    ExternCall(String),
+    UnsupportedOperator(String),
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    EmptyStack,
+    RegisterIndex(usize),
+    Memory(MemoryError),
+    Output(std::io::Error),
+    Assert,
+    AssertEq(i64, i64),
+}
+
+#[derive(Debug)]
+pub enum ReplError {
+    UnknownCommand(String),
+    InvalidArgument(String),
+    MissingArgument(String),
+}
+
+#[derive(Debug)]
+pub enum Warning {
+    UnusedFunction(String),
+    UnusedParam(String, String),
+}
+
 #[derive(Debug)]
 pub struct LocatedError {
     pub loc: Location,
@@ -34,6 +83,31 @@ impl fmt::Display for LocatedError {
     }
 }
 
+/// Problem worth reporting that doesn't stop compilation
+#[derive(Debug)]
+pub struct LocatedWarning {
+    pub loc: Location,
+    pub warning: Warning,
+}
+
+impl LocatedWarning {
+    pub fn new(loc: Location, warning: Warning) -> Self {
+        Self { loc, warning }
+    }
+}
+
+impl fmt::Display for LocatedWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.warning, self.loc)
+    }
+}
+
+impl Error for LocatedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
 impl fmt::Display for LexingError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -47,12 +121,24 @@ impl fmt::Display for LexingError {
                     s
                 )
             }
+            Self::MemoryWidth(ref s) => {
+                write!(
+                    f,
+                    "\t{}\n\t ^\nMemoryWidthError: is not a valid memory access width",
+                    s
+                )
+            }
             Self::ParsingIdentifier(ref s) => write!(
                 f,
                 "\t{}\n\t^\nParsingIdentifierError: invalid identifier",
                 s
             ),
             Self::Syntax(ref s) => write!(f, "\t{}\n\t^\nSyntaxError: invalid syntax", s),
+            Self::UnterminatedString(ref s) => write!(
+                f,
+                "\t{}\n\t^\nUnterminatedStringError: missing closing `\"`",
+                s
+            ),
             Self::UnexpectedEOF => write!(f, "UnexpectedEOFError: unexpected end of file"),
             Self::NoMatch => write!(f, "NoMatchError: found no match"),
         }
@@ -64,3 +150,171 @@ impl Error for LexingError {
         None
     }
 }
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::OutOfBounds(addr, len) => write!(
+                f,
+                "MemoryError: access of {} bytes at address {} is out of bounds",
+                len, addr
+            ),
+        }
+    }
+}
+
+impl Error for MemoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::UnexpectedToken(ref s) => {
+                write!(f, "UnexpectedTokenError: unexpected `{}`", s)
+            }
+            Self::FunctionName => write!(f, "FunctionNameError: expected function name"),
+            Self::TestName => write!(f, "TestNameError: expected test name"),
+            Self::NotEnclosedBlock => write!(f, "NotEnclosedError: not enclosed block"),
+            Self::NotEnclosedFunction => write!(f, "NotEnclosedError: not enclosed function"),
+            Self::NotEnclosedTest => write!(f, "NotEnclosedError: not enclosed test"),
+            Self::NotDefined(ref s) => write!(f, "NotDefinedError: {} is not defined", s),
+            Self::RegisterIndex(i) => {
+                write!(f, "RegisterIndexError: invalid register index {}", i)
+            }
+            Self::TestOnly(ref s) => {
+                write!(f, "TestOnlyError: `{}` can only be used in tests", s)
+            }
+            Self::RecursiveInline(ref s) => {
+                write!(
+                    f,
+                    "RecursiveInlineError: inline function {} calls itself",
+                    s
+                )
+            }
+            Self::InlineParams(ref s) => {
+                write!(
+                    f,
+                    "InlineParamsError: parameters of inline function {} can't be bound, \
+                    its body must only take values it pushed, on every path the same \
+                    number, and leave at most two",
+                    s
+                )
+            }
+            Self::ExternSignature(ref s) => {
+                write!(
+                    f,
+                    "ExternSignatureError: declaration of extern function {} must end with \
+                    `--`, followed by at most one result on the same line",
+                    s
+                )
+            }
+            Self::ExternParams(ref s, n) => {
+                write!(
+                    f,
+                    "ExternParamsError: extern function {} takes {} parameters, at most {} \
+                    can be passed in registers",
+                    s, n, EXTERN_PARAMS
+                )
+            }
+            Self::ExternCall(ref s) => {
+                write!(
+                    f,
+                    "ExternCallError: extern function {} can only be called from compiled code",
+                    s
+                )
+            }
+            Self::UnsupportedOperator(ref s) => {
+                write!(f, "UnsupportedOperatorError: `{}` is not supported yet", s)
+            }
+        }
+    }
+}
+
+impl Error for ParsingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::EmptyStack => write!(f, "StackError: stack is empty"),
+            Self::RegisterIndex(i) => {
+                write!(f, "RegisterIndexError: invalid register index {}", i)
+            }
+            Self::Memory(ref e) => write!(f, "{}", e),
+            Self::Output(ref e) => write!(f, "OutputError: {}", e),
+            Self::Assert => write!(f, "AssertionError: expected a nonzero value, got 0"),
+            Self::AssertEq(expected, got) => {
+                write!(f, "AssertionError: expected {}, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Memory(ref e) => Some(e),
+            Self::Output(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<MemoryError> for RuntimeError {
+    fn from(e: MemoryError) -> Self {
+        Self::Memory(e)
+    }
+}
+
+impl From<std::io::Error> for RuntimeError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Output(e)
+    }
+}
+
+impl fmt::Display for ReplError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::UnknownCommand(ref s) => write!(
+                f,
+                "UnknownCommandError: `{}` is not a command, see `:help`",
+                s
+            ),
+            Self::InvalidArgument(ref s) => {
+                write!(f, "InvalidArgumentError: `{}` is not a valid argument", s)
+            }
+            Self::MissingArgument(ref s) => {
+                write!(f, "MissingArgumentError: `{}` expects more arguments", s)
+            }
+        }
+    }
+}
+
+impl Error for ReplError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::UnusedFunction(ref s) => {
+                write!(f, "UnusedWarning: function {} is never used", s)
+            }
+            Self::UnusedParam(ref s, ref function) => {
+                write!(
+                    f,
+                    "UnusedWarning: parameter {} of {} is never used",
+                    s, function
+                )
+            }
+        }
+    }
+}
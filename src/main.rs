@@ -1,16 +1,24 @@
+mod bench;
 mod compiler;
+mod debugger;
+mod golden;
+mod interpreter;
+mod ir;
 mod lexer;
 mod lib;
 mod repl;
 mod tests;
 
-use clap::Parser;
-use compiler::compile;
+use clap::{ArgEnum, Parser, Subcommand};
+use compiler::{assemble, build, compile, Assembler, Options, Output, Platform};
+use debugger::Debugger;
+use interpreter::{trace::Tracer, Interpreter};
 use lexer::tokenize;
 use lexer::tokens::Token;
 use lib::utils::LocatedResult;
 use repl::Repl;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{stderr, stdout, BufWriter, Write};
 use std::path::PathBuf;
 use std::process::exit;
 
@@ -18,7 +26,11 @@ use std::process::exit;
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Target file
     #[clap(parse(from_os_str))]
     input: Option<PathBuf>,
@@ -26,6 +38,114 @@ struct Args {
     /// Place the output into <OUTPUT>
     #[clap(short, long)]
     output: Option<PathBuf>,
+
+    /// Optimize the generated code at <LEVEL>
+    #[clap(
+        short = 'O',
+        long,
+        value_name = "LEVEL",
+        default_value = "0",
+        possible_values = ["0", "1", "2"]
+    )]
+    opt_level: u8,
+
+    /// Optimize the generated code, the same as -O1
+    #[clap(long, conflicts_with = "opt-level")]
+    optimize: bool,
+
+    /// Kind of output to produce
+    #[clap(long, arg_enum, default_value = "exe")]
+    emit: Emit,
+
+    /// Platform to generate code for
+    #[clap(long, arg_enum, default_value = "x86_64")]
+    target: Platform,
+
+    /// Assembler to build x86_64 code with, nasm if it's on PATH and gas
+    /// otherwise
+    #[clap(long, arg_enum)]
+    assembler: Option<Assembler>,
+
+    /// Link against libc with cc, so extern functions can come from it
+    #[clap(long)]
+    libc: bool,
+
+    /// Link the library <LIB> into the executable, can be repeated
+    #[clap(long = "link-lib", value_name = "LIB")]
+    link_libs: Vec<String>,
+
+    /// Link the object file <OBJ> into the executable, can be repeated
+    #[clap(long = "link-obj", value_name = "OBJ")]
+    link_objs: Vec<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// Executable binary
+    Exe,
+    /// Intermediate representation, printed to stdout unless <OUTPUT> is given
+    Ir,
+    /// Assembly with the inlining decisions, C source for --target=c or
+    /// WebAssembly text for --target=wat, printed to stdout unless <OUTPUT>
+    /// is given
+    Asm,
+    /// ELF object with the exported functions and a C header, x86_64 only
+    Obj,
+    /// Static library with the exported functions and a C header, x86_64
+    /// only
+    Staticlib,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Time compiled programs at every optimization level
+    Bench {
+        /// Program or directory of programs
+        #[clap(parse(from_os_str), default_value = "benches")]
+        path: PathBuf,
+
+        /// Number of runs to take the median time of
+        #[clap(long, default_value = "5")]
+        runs: usize,
+    },
+    /// Step through a program with the interpreter
+    Debug {
+        /// Target file
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Run a program with the interpreter, without compiling it
+    Run {
+        /// Target file
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Log every executed token with the top of the stack after it
+        #[clap(long)]
+        trace: bool,
+
+        /// Only trace tokens executed while <FUNCTION> is active
+        #[clap(long = "trace-fn", value_name = "FUNCTION", requires = "trace")]
+        trace_functions: Vec<String>,
+
+        /// Write the trace into <FILE> instead of stderr
+        #[clap(long, value_name = "FILE", requires = "trace", parse(from_os_str))]
+        trace_out: Option<PathBuf>,
+    },
+    /// Run programs and compare their output with the expected one
+    Test {
+        /// Program or directory of programs
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+
+        /// Compile programs instead of interpreting them
+        #[clap(long)]
+        native: bool,
+
+        /// Overwrite expectations with the actual output
+        #[clap(long)]
+        bless: bool,
+    },
 }
 
 fn read_program(path: &str) -> LocatedResult<Vec<Token>> {
@@ -33,30 +153,146 @@ fn read_program(path: &str) -> LocatedResult<Vec<Token>> {
     tokenize(data.as_str(), path)
 }
 
+fn debug(input: PathBuf) -> i32 {
+    let path = input.to_str().unwrap();
+    let source = fs::read_to_string(path).expect("failed to read from file");
+    match Debugger::new(path, source) {
+        Ok(mut debugger) => debugger.run_loop(),
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn run(input: PathBuf, trace: bool, functions: Vec<String>, out: Option<PathBuf>) -> i32 {
+    let program = match read_program(input.to_str().unwrap()) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let mut interpreter = Interpreter::new(stdout());
+    if trace {
+        let output: Box<dyn Write> = match out {
+            Some(path) => match File::create(&path) {
+                Ok(f) => Box::new(BufWriter::new(f)),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    return 1;
+                }
+            },
+            None => Box::new(stderr()),
+        };
+        interpreter.set_tracer(Tracer::new(output, functions));
+    }
+
+    match interpreter.eval(program) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Writes the IR or the assembly of `program` into `out`, or stdout if no
+/// output is given
+fn emit(program: &[Token], kind: Emit, out: Option<PathBuf>, options: &Options) -> i32 {
+    let built = match kind {
+        Emit::Ir => build(program, options).map(|(module, w)| (module.to_string(), w)),
+        _ => assemble(program, options),
+    };
+    let text = match built {
+        Ok((text, warnings)) => {
+            warnings.iter().for_each(|w| eprintln!("{}", w));
+            text
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let written = match out {
+        Some(path) => fs::write(&path, text),
+        None => write!(stdout(), "{}", text),
+    };
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
 fn main() {
     let args = Args::parse();
+    match args.command {
+        Some(Command::Bench { path, runs }) => exit(bench::run(&path, runs)),
+        Some(Command::Debug { input }) => exit(debug(input)),
+        Some(Command::Run {
+            input,
+            trace,
+            trace_functions,
+            trace_out,
+        }) => exit(run(input, trace, trace_functions, trace_out)),
+        Some(Command::Test {
+            path,
+            native,
+            bless,
+        }) => exit(golden::run(&path, native, bless)),
+        None => {}
+    }
+
     match args.input {
         Some(input) => {
-            let mut program = match read_program(input.to_str().unwrap()) {
+            let program = match read_program(input.to_str().unwrap()) {
                 Ok(p) => p,
                 Err(e) => {
                     eprintln!("{}", e);
                     exit(1);
                 }
             };
+            let options = Options {
+                opt_level: if args.optimize { 1 } else { args.opt_level },
+                target: args.target,
+                assembler: args.assembler.unwrap_or_else(Assembler::detect),
+                libc: args.libc,
+                link_libs: args.link_libs,
+                link_objs: args.link_objs,
+                output: match args.emit {
+                    Emit::Obj => Output::Obj,
+                    Emit::Staticlib => Output::Staticlib,
+                    _ => Output::Exe,
+                },
+            };
+            let default = match args.emit {
+                Emit::Ir | Emit::Asm => {
+                    exit(emit(&program, args.emit, args.output, &options));
+                }
+                Emit::Exe => "output",
+                Emit::Obj => "output.o",
+                Emit::Staticlib => "liboutput.a",
+            };
             let out = args.output.unwrap_or_else(|| {
-                let mut default = PathBuf::new();
-                default.set_file_name("output");
-                default
+                let mut path = PathBuf::new();
+                path.set_file_name(default);
+                path
             });
-            if let Err(e) = compile(&mut program, out.to_str().unwrap()) {
-                eprintln!("{}", e);
-                exit(1);
-            };
+            match compile(&program, out.to_str().unwrap(), &options) {
+                Ok(warnings) => warnings.iter().for_each(|w| eprintln!("{}", w)),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
         }
         _ => {
             let mut repl = Repl::new(">> ");
-            repl.run_loop();
+            exit(repl.run_loop());
         }
     }
 }
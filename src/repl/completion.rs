@@ -0,0 +1,63 @@
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+
+const KEYWORDS: [&str; 19] = [
+    "if", "else", "while", "do", "end", "fn", "inline", "noinline", "extern", "export", "test", "mem", "dup",
+    "drop", "swap", "over", "rot", "assert", "assert_eq",
+];
+
+const COMMANDS: [&str; 10] = [
+    ":stack", ":mem", ":regs", ":words", ":reset", ":load", ":save", ":asm", ":help", ":quit",
+];
+
+/// Completes keywords, defined functions and shell commands
+#[derive(Default)]
+pub struct ReplHelper {
+    pub words: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map_or(0, |idx| idx + 1);
+        let prefix = &line[start..pos];
+
+        let candidates = if start == 0 && prefix.starts_with(':') {
+            COMMANDS
+                .iter()
+                .filter(|c| c.starts_with(prefix))
+                .map(|c| c.to_string())
+                .collect()
+        } else {
+            let mut candidates: Vec<String> = KEYWORDS
+                .iter()
+                .map(|k| k.to_string())
+                .chain(self.words.iter().cloned())
+                .filter(|w| w.starts_with(prefix))
+                .collect();
+            candidates.sort();
+            candidates
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,280 @@
+pub(crate) mod completion;
+
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{stderr, stdout, BufWriter, Stderr, Stdout, Write},
+    path::PathBuf,
+};
+
+use rustyline::{error::ReadlineError, Editor};
+
+use crate::{
+    compiler::{translate, Options},
+    interpreter::{format_stack, program::nesting, Interpreter},
+    lexer::tokenize,
+    lib::{
+        constants::{PKG_DESCRIPTION, PKG_NAME, VERSION},
+        errors::{LexingError, LocatedError, ReplError},
+        utils::LocatedResult,
+    },
+};
+
+use self::completion::ReplHelper;
+
+const HELP: &str = "\
+:stack [on|off]   print the stack, or toggle printing it after every line
+:mem <addr> <len> dump <len> bytes of memory starting at <addr> (e.g. `mem+8`)
+:regs             print registers
+:words            list defined functions
+:reset            forget the stack, memory, registers and functions
+:load <file>      evaluate a file
+:save <file>      save every successfully evaluated line to a file
+:asm <code>       show the assembly the compiler emits for <code>
+:help             show this message
+:quit             exit the shell";
+
+enum Input {
+    Source(String),
+    Command(String),
+}
+
+pub struct Repl<W: Write> {
+    interpreter: Interpreter<W>,
+    session: Vec<String>,
+    show_stack: bool,
+    prompt: String,
+    continuation_prompt: String,
+    history_file: Option<PathBuf>,
+    editor: Editor<ReplHelper>,
+    error_handle: BufWriter<Stderr>,
+}
+
+impl Repl<BufWriter<Stdout>> {
+    pub fn new(prompt: &str) -> Self {
+        Self::with_output(prompt, BufWriter::new(stdout()))
+    }
+}
+
+impl<W: Write> Repl<W> {
+    pub fn with_output(prompt: &str, output: W) -> Self {
+        let stderr = stderr();
+        let mut editor = Editor::new();
+        editor.set_helper(Some(ReplHelper::default()));
+        Repl {
+            interpreter: Interpreter::new(output),
+            session: Vec::new(),
+            show_stack: false,
+            prompt: String::from(prompt),
+            continuation_prompt: String::from(".. "),
+            history_file: env::var_os("HOME").map(|home| PathBuf::from(home).join(".lcl_history")),
+            editor,
+            error_handle: BufWriter::new(stderr),
+        }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        let line = self.editor.readline(prompt)?;
+        if !line.trim().is_empty() {
+            self.editor.add_history_entry(line.as_str());
+        }
+        Ok(line)
+    }
+
+    /// Reads a command, or lines until every block and definition opened in
+    /// them is closed
+    fn read(&mut self) -> Result<Input, ReadlineError> {
+        let line = self.read_line(&self.prompt.clone())?;
+        if line.trim_start().starts_with(':') {
+            return Ok(Input::Command(line.trim().to_string()));
+        }
+
+        let mut src = line;
+        src.push('\n');
+        while matches!(tokenize(&src, "<stdin>"), Ok(tokens) if nesting(&tokens) > 0) {
+            let line = self.read_line(&self.continuation_prompt.clone())?;
+            src.push_str(&line);
+            src.push('\n');
+        }
+        Ok(Input::Source(src))
+    }
+
+    pub(crate) fn evaluate(&mut self, src: &str, file: &str) -> LocatedResult<()> {
+        let tokens = tokenize(src, file)?;
+        let result = self.interpreter.eval_atomic(tokens);
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.words = self.interpreter.functions().keys().cloned().collect();
+        }
+        result?;
+        self.session.push(src.to_string());
+        Ok(())
+    }
+
+    /// Prints `error` along with the line of `src` it points at
+    fn report(&mut self, src: &str, error: &LocatedError) {
+        writeln!(self.error_handle, "{}", error).unwrap();
+        if error.error.is::<LexingError>() {
+            // Lexing errors already show the offending word
+            return;
+        }
+        if let Some(line) = src.lines().nth(error.loc.row - 1) {
+            let padding: String = line
+                .chars()
+                .take(error.loc.col - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            writeln!(self.error_handle, "\t{}\n\t{}^", line, padding).unwrap();
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn output(&mut self) -> &mut W {
+        self.interpreter.output()
+    }
+
+    /// Executes a `:command`, returns `false` if the shell should exit
+    pub(crate) fn command(&mut self, line: &str) -> Result<bool, Box<dyn Error>> {
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let missing = || ReplError::MissingArgument(name.to_string());
+
+        match name {
+            ":stack" => match args.first() {
+                Some(&"on") => self.show_stack = true,
+                Some(&"off") => self.show_stack = false,
+                Some(other) => return Err(ReplError::InvalidArgument(other.to_string()).into()),
+                None => {
+                    let stack = format_stack(self.interpreter.stack());
+                    writeln!(self.interpreter.output(), "{}", stack)?;
+                }
+            },
+            ":mem" => {
+                let addr = args.first().ok_or_else(missing)?;
+                let addr = self
+                    .interpreter
+                    .memory()
+                    .parse_address(addr)
+                    .ok_or_else(|| ReplError::InvalidArgument(addr.to_string()))?;
+                let len = args.get(1).ok_or_else(missing)?;
+                let len = len
+                    .parse::<usize>()
+                    .map_err(|_| ReplError::InvalidArgument(len.to_string()))?;
+
+                let dump = self.interpreter.memory().dump(addr, len)?;
+                write!(self.interpreter.output(), "{}", dump)?;
+            }
+            ":regs" => {
+                let registers = *self.interpreter.registers();
+                for (i, v) in registers.iter().enumerate() {
+                    writeln!(self.interpreter.output(), "r{} = {}", i + 1, v)?;
+                }
+            }
+            ":words" => {
+                let mut words: Vec<(String, String)> = self
+                    .interpreter
+                    .functions()
+                    .values()
+                    .map(|f| {
+                        let keyword = match (f.inline, f.noinline) {
+                            (true, _) => "inline",
+                            (false, true) => "noinline fn",
+                            (false, false) => "fn",
+                        };
+                        let mut word = format!("{} {}", keyword, f.name);
+                        for param in f.params.iter() {
+                            word.push_str(&format!(" {}", param));
+                        }
+                        (f.name.clone(), word)
+                    })
+                    .collect();
+                words.sort_by(|a, b| a.0.cmp(&b.0));
+                for (_, word) in words {
+                    writeln!(self.interpreter.output(), "{}", word)?;
+                }
+            }
+            ":reset" => {
+                self.interpreter.reset();
+                self.session.clear();
+            }
+            ":load" => {
+                let file = args.first().ok_or_else(missing)?;
+                let mut src = fs::read_to_string(file)?;
+                if !src.ends_with('\n') {
+                    src.push('\n');
+                }
+                if let Err(e) = self.evaluate(&src, file) {
+                    self.report(&src, &e);
+                }
+            }
+            ":save" => {
+                let file = args.first().ok_or_else(missing)?;
+                fs::write(file, self.session.concat())?;
+            }
+            ":asm" => {
+                let tokens = tokenize(rest, "<stdin>")?;
+                let asm = translate(&tokens, &Options::default())?;
+                write!(self.interpreter.output(), "{}", asm)?;
+            }
+            ":help" => writeln!(self.interpreter.output(), "{}", HELP)?,
+            ":quit" | ":q" => return Ok(false),
+            other => return Err(ReplError::UnknownCommand(other.to_string()).into()),
+        }
+        Ok(true)
+    }
+
+    /// Runs the shell until `:quit`, end of input or interrupt, returns the
+    /// exit status
+    pub fn run_loop(&mut self) -> i32 {
+        println!(
+            "{} {} interactive shell\n{}",
+            PKG_NAME, VERSION, PKG_DESCRIPTION
+        );
+        if let Some(history) = &self.history_file {
+            // There is no history yet on the first run
+            let _ = self.editor.load_history(history);
+        }
+
+        let status = loop {
+            match self.read() {
+                Ok(Input::Command(line)) => match self.command(&line) {
+                    Ok(true) => {}
+                    Ok(false) => break 0,
+                    Err(e) => writeln!(self.error_handle, "{}", &e).unwrap(),
+                },
+                Ok(Input::Source(src)) => {
+                    let result = self.evaluate(&src, "<stdin>");
+                    self.interpreter.output().flush().unwrap();
+                    if let Err(e) = &result {
+                        self.report(&src, e);
+                    }
+                    if self.show_stack {
+                        let stack = format_stack(self.interpreter.stack());
+                        writeln!(self.interpreter.output(), "{}", stack).unwrap();
+                    }
+                    if result.is_ok() {
+                        writeln!(self.interpreter.output(), "ok").unwrap();
+                    }
+                }
+                Err(ReadlineError::Eof) => break 0,
+                Err(ReadlineError::Interrupted) => break 130,
+                Err(e) => {
+                    writeln!(self.error_handle, "{}", &e).unwrap();
+                    break 1;
+                }
+            }
+
+            self.interpreter.output().flush().unwrap();
+            self.error_handle.flush().unwrap();
+        };
+
+        if let Some(history) = &self.history_file {
+            if let Err(e) = self.editor.save_history(history) {
+                writeln!(self.error_handle, "failed to save history: {}", e).unwrap();
+            }
+        }
+        self.interpreter.output().flush().unwrap();
+        self.error_handle.flush().unwrap();
+        status
+    }
+}
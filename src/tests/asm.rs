@@ -0,0 +1,62 @@
+//! Golden files of the assembly every target emits for the programs in
+//! `tests/asm`, stored next to them as `<name>.<target>.<ext>`. Setting
+//! `LCL_BLESS` rewrites them with the current output instead.
+
+#![allow(unused_imports)]
+use std::{
+    env,
+    fs::{read_dir, read_to_string, write},
+    path::Path,
+};
+
+use crate::{
+    compiler::{assemble, Assembler, Options, Platform},
+    golden::diff,
+    lexer::tokenize,
+};
+
+/// Targets with golden files, the assembler their output is for and the
+/// extension of their assembly
+#[cfg(test)]
+const TARGETS: [(Platform, Assembler, &str); 3] = [
+    (Platform::X86_64, Assembler::Nasm, "x86_64.asm"),
+    (Platform::X86_64, Assembler::Gas, "x86_64.s"),
+    (Platform::Aarch64, Assembler::Gas, "aarch64.s"),
+];
+
+#[test]
+fn asm_golden_files() {
+    let mut programs: Vec<_> = read_dir("tests/asm")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lcl"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no programs found");
+
+    let bless = env::var_os("LCL_BLESS").is_some();
+    for path in programs {
+        let src = read_to_string(&path).unwrap();
+        let tokens = tokenize(&src, path.to_str().unwrap()).unwrap();
+        for (target, assembler, ext) in TARGETS {
+            let options = Options {
+                target,
+                assembler,
+                ..Default::default()
+            };
+            let (asm, _) = assemble(&tokens, &options).unwrap();
+            let golden = path.with_extension(ext);
+            if bless {
+                write(&golden, &asm).unwrap();
+                continue;
+            }
+            let expected = read_to_string(&golden).unwrap_or_default();
+            assert!(
+                expected == asm,
+                "{} changed, rerun with LCL_BLESS=1 if it's expected:\n{}",
+                golden.display(),
+                diff(&expected, &asm)
+            );
+        }
+    }
+}
@@ -1,18 +1,33 @@
 #![allow(unused_imports)]
 use super::compiler_test;
-use crate::compiler::compile;
+#[cfg(test)]
+use super::differential::{c_available, differential, interpret, native_available, run_native};
+#[cfg(test)]
+use crate::compiler::count;
+use crate::compiler::{assemble, compile, translate, Assembler, Options, Output, Platform};
 use crate::lexer::{tokenize, tokens::Token};
-use std::fs::remove_file;
+use std::fs::{self, remove_file};
 use std::process::Command;
-use std::str::from_utf8;
 
 // Push/Pop
 compiler_test!(compile_push_int, "!1 ." => "1\n");
 compiler_test!(compile_registers, "1 @r1 !r1 ." => "1\n");
+compiler_test!(compile_registers_survive_builtins, "1 @r1 2 @r2 3 @r3 4 @r4 2 3 + 1 2 < 5 dup swap over rot . . . . . !r1 !r2 !r3 !r4 . . . ." => "5\n5\n5\n1\n5\n4\n3\n2\n1\n");
+compiler_test!(compile_spilled_registers, "5 @r5 6 @r8 !r5 @r6 !r6 !r8 + . 7 @r7 !r7 @r1 !r1 ." => "11\n7\n");
+compiler_test!(
+    compile_registers_across_calls,
+    "fn f do !r5 1 + @r5 9 @r2 end
+    1 @r5 f f !r5 . 1 . !r2 ." => "3\n1\n9\n"
+);
 
 // Arithmetics
 compiler_test!(compile_plus, "2 2 + ." => "4\n");
 compiler_test!(compile_minus, "5 2 - ." => "3\n");
+compiler_test!(compile_negative, "0 1 - . 2 12 - . 0 9223372036854775807 - 1 - ." => "-1\n-10\n-9223372036854775808\n");
+compiler_test!(
+    compile_negative_in_functions,
+    "fn neg a do 0 a - end 7 neg . 0 neg . 9223372036854775807 neg neg ." => "-7\n0\n9223372036854775807\n"
+);
 
 // Comparison
 compiler_test!(compile_less_true, "1 2 < ." => "1\n");
@@ -34,6 +49,11 @@ compiler_test!(compile_rot, "1 2 3 rot . . ." => "1\n3\n2\n");
 // Memory
 compiler_test!(compile_memory_1, "mem 1 @ mem ! ." => "1\n");
 compiler_test!(compile_memory_2, "mem 8 + 2 @ mem 8 + ! ." => "2\n");
+compiler_test!(compile_memory_byte, "mem 258 @ mem !u8 . mem 1 + !u8 ." => "2\n1\n");
+compiler_test!(compile_memory_store_byte, "mem 258 @u8 mem ! ." => "2\n");
+compiler_test!(compile_memory_sign_extend, "mem 255 @u8 mem !i8 . mem 200 @u8 mem !i8 . mem 32768 @u16 mem !i16 ." => "-1\n-56\n-32768\n");
+compiler_test!(compile_memory_zero_extend, "mem 65535 @u16 mem !u16 ." => "65535\n");
+compiler_test!(compile_memory_dword, "mem 4294967295 @ mem !i32 . mem !u32 ." => "-1\n4294967295\n");
 
 // Control flow
 compiler_test!(
@@ -84,9 +104,520 @@ compiler_test!(
     end
     1 2 my_swap . ." => "1\n2\n"
 );
+compiler_test!(
+    compile_inline_fn_function,
+    "inline fn my_swap do
+        @r1 @r2
+        !r1 !r2
+    end
+    1 2 my_swap . ." => "1\n2\n"
+);
+compiler_test!(
+    compile_inline_params,
+    "inline quadruple x do
+        x x + x x + +
+    end
+    inline both a b do b a end
+    inline choose a b c do a 0 > if b else c end end
+    fn f x do 3 quadruple x + end
+    1 f . 2 quadruple . 1 2 both . . 1 2 0 choose . 1 2 3 choose ." => "13\n8\n2\n1\n1\n2\n"
+);
+
+compiler_test!(
+    compile_without_tests,
+    "fn test do
+        1 .
+    end
+    test \"fails\" do
+        0 assert
+        while 1 do end
+    end
+    test" => "1\n"
+);
 
 compiler_test!(FAIL: unexpected_else, "1 2 3 else 1 2 3");
 compiler_test!(FAIL: unexpected_do, "1 2 3 do 1 2 3");
 compiler_test!(FAIL: unexpected_end_of_block, "1 2 3 end 1 2 3");
 compiler_test!(FAIL: not_defined, "asd");
 compiler_test!(FAIL: unfinished_function, "fn test do");
+
+/// Instructions emitted for `src` at each optimization level
+#[cfg(test)]
+fn instructions(src: &str) -> [usize; 3] {
+    let tokens = tokenize(src, "<test>").unwrap();
+    [0, 1, 2].map(|opt_level| {
+        count(
+            &translate(
+                &tokens,
+                &Options {
+                    opt_level,
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        )
+    })
+}
+
+#[test]
+fn optimize_folds_constants() {
+    let tokens = tokenize("2 2 + 3 < .", "<test>").unwrap();
+    let asm = translate(
+        &tokens,
+        &Options {
+            opt_level: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        asm,
+        "\t; Push 0\n\tmov  rax, 0\n\t; Dot\n\tmov rdi, rax\n\tcall print\n"
+    );
+}
+
+#[test]
+fn optimize_drops_fewer_instructions() {
+    let src = "fn f a do a a + drop a end 1 f . mem 2 @ mem ! drop";
+    let [plain, optimized, _] = instructions(src);
+    assert!(optimized < plain, "{} >= {}", optimized, plain);
+
+    let tokens = tokenize(src, "<test>").unwrap();
+    let asm = translate(
+        &tokens,
+        &Options {
+            opt_level: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(!asm.contains("xor rax, rax"), "{}", asm);
+}
+
+#[test]
+fn optimize_keeps_labels() {
+    let src = "0 while dup 3 < do dup . 1 + end drop";
+    let [plain, optimized, cached] = instructions(src);
+    assert!(optimized < plain, "{} >= {}", optimized, plain);
+    assert!(cached < optimized, "{} >= {}", cached, optimized);
+}
+
+#[test]
+fn optimize_keeps_memory_to_memory_moves_apart() {
+    let tokens = tokenize("5 @r5 !r5 @r6", "<test>").unwrap();
+    let asm = translate(
+        &tokens,
+        &Options {
+            opt_level: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(
+        asm.lines()
+            .all(|l| !l.starts_with("\tmov") || l.matches('[').count() < 2),
+        "{}",
+        asm
+    );
+    assert!(asm.contains("\tpop QWORD [regs + 8]\n"), "{}", asm);
+}
+
+#[test]
+fn optimize_caches_top_of_stack() {
+    let tokens = tokenize(
+        "noinline fn f a b do a b + b swap - dup < end 1 2 f",
+        "<test>",
+    )
+    .unwrap();
+    let asm = translate(
+        &tokens,
+        &Options {
+            opt_level: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Only the frame of the function goes through memory
+    let stack_ops = asm
+        .lines()
+        .take_while(|l| *l != "\tret")
+        .filter(|l| l.starts_with("\tpush") || l.starts_with("\tpop"))
+        .collect::<Vec<_>>();
+    assert_eq!(stack_ops, ["\tpush rbp", "\tpop rbp"], "{}", asm);
+}
+
+compiler_test!(compile_optimized_constants, "1 2 3 rot + swap - dup . 0 = if 7 . else 8 . end" => "2\n8\n");
+compiler_test!(compile_cached_stack, "mem 7 @ 1 2 3 rot over swap drop - mem !u8 + dup 1 > . ." => "1\n7\n");
+compiler_test!(
+    compile_cached_rot,
+    "fn f a b c do
+        a b c rot rot - +
+    end
+    1 2 3 f . 4 5 6 rot . . ." => "2\n4\n6\n5\n"
+);
+compiler_test!(
+    compile_optimized_loop,
+    "0 while dup 3 < do
+        dup .
+        1 +
+    end
+    drop" => "0\n1\n2\n"
+);
+compiler_test!(
+    compile_deep_tail_recursion,
+    "fn countdown n do
+        n 0 > if n 1 - countdown end
+    end
+    1000000 countdown 7 ." => "7\n"
+);
+compiler_test!(
+    compile_mutual_tail_recursion,
+    "fn ping n do n 0 = if 1 . else n 1 - pong end end
+    fn pong n do n 0 = if 0 . else n 1 - ping end end
+    1000001 ping" => "0\n"
+);
+
+#[test]
+fn compile_tail_calls_as_jumps() {
+    let tokens = tokenize(
+        "fn countdown n do n 0 > if n 1 - countdown end end 3 countdown",
+        "<test>",
+    )
+    .unwrap();
+    for opt_level in 0..=2 {
+        let asm = translate(
+            &tokens,
+            &Options {
+                opt_level,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let body: Vec<&str> = asm
+            .lines()
+            .skip_while(|l| *l != "countdown:")
+            .take_while(|l| *l != "\tret")
+            .collect();
+        assert!(body.contains(&"\tjmp countdown"));
+        assert!(!body.contains(&"\tcall countdown"));
+    }
+}
+compiler_test!(
+    compile_inlined_functions,
+    "fn sub a b do a b - end
+    fn max a b do a b > if a @r1 else b @r1 end !r1 end
+    noinline fn add a b do a b + end
+    7 3 sub . 2 9 max . 8 2 add 1 sub ." => "-4\n9\n-9\n"
+);
+
+#[test]
+fn assemble_shows_inlining_decisions() {
+    let tokens = tokenize("fn f do 1 end noinline fn g do 2 end f g + .", "<test>").unwrap();
+    let (asm, _) = assemble(
+        &tokens,
+        &Options {
+            opt_level: 1,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(asm.contains("; f: inlined\n"));
+    assert!(asm.contains("; g: not inlined, marked noinline\n"));
+    assert!(!asm.contains("call f"));
+    assert!(asm.contains("call g"));
+}
+
+#[test]
+fn emit_functions_in_definition_order() {
+    let tokens = tokenize("fn z do 1 . end fn a do z end fn m do a end m", "<test>").unwrap();
+    let asm = translate(&tokens, &Options::default()).unwrap();
+    let functions: Vec<&str> = asm
+        .lines()
+        .filter(|l| l.ends_with(':') && !l.starts_with('.'))
+        .collect();
+    assert_eq!(functions, ["z:", "a:", "m:"]);
+    assert_eq!(asm, translate(&tokens, &Options::default()).unwrap());
+}
+
+#[test]
+fn c_target_emits_c_functions() {
+    let src = "fn count-down n do n 0 > if n 1 - count-down end end 3 count-down";
+    let tokens = tokenize(src, "<test>").unwrap();
+    let options = Options {
+        target: Platform::C,
+        ..Default::default()
+    };
+    let (c, _) = assemble(&tokens, &options).unwrap();
+    assert!(
+        c.contains("static void lcl_count_2d_down(void);\n"),
+        "{}",
+        c
+    );
+    // Calling itself in tail position loops instead of growing the C stack
+    assert!(c.contains("\tgoto entry;\n"), "{}", c);
+    assert!(c.contains("int main(void)\n"), "{}", c);
+}
+
+#[test]
+fn wat_target_dispatches_on_labels() {
+    let src = "fn ping n do n 0 = if 1 . else n 1 - pong end end
+    fn pong n do n 0 = if 0 . else n 1 - ping end end
+    3 ping";
+    let tokens = tokenize(src, "<test>").unwrap();
+    let options = Options {
+        target: Platform::Wat,
+        ..Default::default()
+    };
+    let (wat, _) = assemble(&tokens, &options).unwrap();
+    assert!(wat.contains("  (func $lcl_ping\n"), "{}", wat);
+    assert!(wat.contains("(func $print (param i64))"), "{}", wat);
+    // `if` and `else` split the body into three blocks
+    assert!(wat.contains("br_table $b0 $b1 $b2\n"), "{}", wat);
+    assert!(wat.contains("return_call $lcl_pong\n"), "{}", wat);
+    assert!(wat.contains("  (func (export \"main\")\n"), "{}", wat);
+}
+
+#[test]
+fn extern_calls_align_the_stack() {
+    let src = "extern fn labs x -- r\n0 3 - labs .";
+    let tokens = tokenize(src, "<test>").unwrap();
+    for opt_level in 0..=2 {
+        let options = Options {
+            opt_level,
+            libc: true,
+            ..Default::default()
+        };
+        let (asm, _) = assemble(&tokens, &options).unwrap();
+        assert!(asm.contains("extern labs\n"), "{}", asm);
+        assert!(
+            asm.contains("\tand rsp, -16\n\txor eax, eax\n\tcall labs\n"),
+            "{}",
+            asm
+        );
+        assert!(asm.contains("\nmain:\n"), "{}", asm);
+        assert!(asm.contains("\tcall exit\n"), "{}", asm);
+    }
+}
+
+#[test]
+fn unsupported_operators_are_errors_on_every_target() {
+    let tokens = tokenize("6 3 /", "<test>").unwrap();
+    for target in [
+        Platform::X86_64,
+        Platform::Aarch64,
+        Platform::C,
+        Platform::Wat,
+    ] {
+        for opt_level in 0..=2 {
+            let options = Options {
+                target,
+                opt_level,
+                ..Default::default()
+            };
+            assert!(assemble(&tokens, &options).is_err());
+        }
+    }
+}
+
+#[test]
+fn compile_aarch64_needs_its_binutils() {
+    let cross = Command::new("aarch64-linux-gnu-as")
+        .arg("--version")
+        .output();
+    if std::env::consts::ARCH == "aarch64" || cross.is_ok() {
+        return;
+    }
+    let outfile = "src/tests/test_compile_aarch64_needs_its_binutils";
+    let tokens = tokenize("1 2 + .", "<test>").unwrap();
+    let options = Options {
+        target: Platform::Aarch64,
+        ..Default::default()
+    };
+    let error = compile(&tokens, outfile, &options).unwrap_err();
+    assert!(error.to_string().contains("--emit=asm"), "{}", error);
+    assert!(remove_file(format!("{}.s", outfile)).is_err());
+}
+
+#[test]
+fn extern_calls_need_assembly() {
+    let src = "extern fn labs x -- r\n0 3 - labs .";
+    let tokens = tokenize(src, "<test>").unwrap();
+    for target in [Platform::C, Platform::Wat] {
+        let options = Options {
+            target,
+            ..Default::default()
+        };
+        assert!(assemble(&tokens, &options).is_err());
+    }
+}
+
+#[test]
+fn compile_extern_calls_against_libc() {
+    if !native_available() || !c_available() {
+        return;
+    }
+    // `exit` flushes what `printf` buffered, after the direct writes of `.`
+    let src = "extern fn labs x -- r
+    extern fn printf format x -- n
+    fn abs x do x labs end
+    mem 37 @u8 mem 1 + 108 @u8 mem 2 + 100 @u8 mem 3 + 10 @u8
+    0 7 - abs . 0 12 - abs mem printf .";
+    for opt_level in 0..=2 {
+        let options = Options {
+            opt_level,
+            assembler: Assembler::detect(),
+            libc: true,
+            ..Default::default()
+        };
+        let outfile = format!("src/tests/test_extern_calls_{}", opt_level);
+        assert_eq!(
+            run_native(src, "<test>", &outfile, &options),
+            ("7\n3\n12\n".to_string(), 0)
+        );
+    }
+}
+
+#[test]
+fn exported_functions_follow_the_c_convention() {
+    let src = "extern fn labs x -- r
+    export fn dist a b do a b - labs end
+    export fn base do mem end
+    1 2 dist .";
+    let tokens = tokenize(src, "<test>").unwrap();
+    for opt_level in 0..=2 {
+        let options = Options {
+            opt_level,
+            assembler: Assembler::Nasm,
+            output: Output::Obj,
+            ..Default::default()
+        };
+        let (asm, _) = assemble(&tokens, &options).unwrap();
+        assert!(asm.contains("default rel\n"), "{}", asm);
+        assert!(asm.contains("global lcl_dist\nlcl_dist:\n"), "{}", asm);
+        // `a` is the first argument and ends on top of the stack
+        assert!(
+            asm.contains("\tpush rsi\n\tpush rdi\n\tcall dist\n\tadd rsp, 16\n"),
+            "{}",
+            asm
+        );
+        assert!(asm.contains("\tmov r12, [saved + 0]\n"), "{}", asm);
+        assert!(asm.contains("\tpop r12\n"), "{}", asm);
+        assert!(asm.contains("\tcall labs wrt ..plt\n"), "{}", asm);
+        assert!(asm.contains(", [mem]\n"), "{}", asm);
+        // The top-level code and the runtime starting it are left out
+        assert!(!asm.contains("_start"), "{}", asm);
+        assert!(!asm.contains("syscall\n\tret"), "{}", asm);
+        assert!(!asm.contains("call print"), "{}", asm);
+    }
+}
+
+#[test]
+fn gas_addresses_exported_code_relative_to_rip() {
+    let src = "extern fn labs x -- r\nexport fn f x do x labs @r5 mem !r5 + end";
+    let options = Options {
+        assembler: Assembler::Gas,
+        output: Output::Obj,
+        ..Default::default()
+    };
+    let (asm, _) = assemble(&tokenize(src, "<test>").unwrap(), &options).unwrap();
+    assert!(!asm.contains("default rel"), "{}", asm);
+    assert!(asm.contains("QWORD PTR [rip + regs]"), "{}", asm);
+    assert!(asm.contains("[rip + mem]"), "{}", asm);
+    assert!(asm.contains("[rip + saved + 8]"), "{}", asm);
+    assert!(asm.contains("[rbp + 16]"), "{}", asm);
+    assert!(asm.contains("\tcall labs@PLT\n"), "{}", asm);
+}
+
+#[test]
+fn exports_need_x86_64_and_few_params() {
+    let options = Options {
+        output: Output::Staticlib,
+        ..Default::default()
+    };
+    let tokens = tokenize("export fn f a b c d e f g do end", "<test>").unwrap();
+    assert!(assemble(&tokens, &options).is_err());
+
+    let tokens = tokenize("export fn f a do end", "<test>").unwrap();
+    assert!(assemble(&tokens, &options).is_ok());
+    let options = Options {
+        target: Platform::Aarch64,
+        ..options
+    };
+    assert!(assemble(&tokens, &options).is_err());
+}
+
+#[test]
+fn compile_exported_functions_for_c() {
+    if !native_available() || !c_available() {
+        return;
+    }
+    let src = "extern fn labs x -- r
+    fn twice x do x x + end
+    export fn dist a b do a b - labs end
+    export fn quad x do x twice twice end
+    export fn keep v do v @r1 mem v @ end
+    export fn kept do !r1 mem ! + end
+    export fn show x do x . end
+    kept .";
+    let c = "#include <stdio.h>
+#include \"test_exports.h\"
+int main(void) {
+    lcl_keep(21);
+    printf(\"%ld %ld %ld\\n\", (long)lcl_dist(3, 10), (long)lcl_quad(5), (long)lcl_kept());
+    fflush(stdout);
+    lcl_show(7);
+    return 0;
+}
+";
+    fs::write("src/tests/test_exports.c", c).unwrap();
+    for (output, lib) in [
+        (Output::Obj, "src/tests/test_exports.o"),
+        (Output::Staticlib, "src/tests/libtest_exports.a"),
+    ] {
+        for opt_level in 0..=2 {
+            let options = Options {
+                opt_level,
+                assembler: Assembler::detect(),
+                output,
+                ..Default::default()
+            };
+            let tokens = tokenize(src, "<test>").unwrap();
+            compile(&tokens, lib, &options).unwrap();
+            // The header is named after the library
+            if output == Output::Staticlib {
+                fs::rename("src/tests/libtest_exports.h", "src/tests/test_exports.h").unwrap();
+            }
+            let header = fs::read_to_string("src/tests/test_exports.h").unwrap();
+            assert!(
+                header.contains("int64_t lcl_dist(int64_t, int64_t);\n"),
+                "{}",
+                header
+            );
+            assert!(header.contains("void lcl_show(int64_t);\n"), "{}", header);
+            assert!(!header.contains("twice"), "{}", header);
+
+            let exe = "src/tests/test_exports";
+            let built = Command::new("cc")
+                .args(["-o", exe, "src/tests/test_exports.c", lib])
+                .output()
+                .unwrap();
+            assert!(built.status.success(), "{:?}", built);
+            let output = Command::new(exe).output().unwrap();
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "7 20 42\n7\n");
+            remove_file(exe).unwrap();
+        }
+    }
+    for file in [
+        "test_exports.c",
+        "test_exports.h",
+        "test_exports.o",
+        "test_exports.asm",
+        "test_exports.s",
+        "libtest_exports.a",
+        "libtest_exports.o",
+        "libtest_exports.asm",
+        "libtest_exports.s",
+    ] {
+        let _ = remove_file(format!("src/tests/{}", file));
+    }
+}
@@ -0,0 +1,175 @@
+#![allow(unused_imports)]
+use crate::debugger::Debugger;
+use crate::interpreter::memory::Memory;
+use std::str::from_utf8;
+
+#[cfg(test)]
+const PROGRAM: &str = "fn inc a do
+    a 1 +
+end
+fn twice a do
+    a inc inc
+end
+3 twice .
+5 @r2
+mem 258 @
+";
+
+#[cfg(test)]
+fn debugger() -> Debugger<Vec<u8>> {
+    Debugger::with_output("dbg.lcl", PROGRAM.to_string(), Vec::new()).unwrap()
+}
+
+/// Runs `command` and returns what it printed
+#[cfg(test)]
+fn run(debugger: &mut Debugger<Vec<u8>>, command: &str) -> String {
+    assert!(debugger.command(command).unwrap(), "{} quit", command);
+    let out = from_utf8(debugger.output()).unwrap().to_string();
+    debugger.output().clear();
+    out
+}
+
+#[test]
+fn debugger_lists_the_next_token() {
+    let mut debugger = debugger();
+    assert_eq!(
+        run(&mut debugger, "list"),
+        "dbg.lcl:7:1\n\t3 twice .\n\t^\n"
+    );
+}
+
+#[test]
+fn debugger_stops_at_breakpoints() {
+    let mut debugger = debugger();
+    assert_eq!(
+        run(&mut debugger, "break 2"),
+        "breakpoint #1 at dbg.lcl:2\n"
+    );
+    assert_eq!(
+        run(&mut debugger, "b dbg.lcl:8"),
+        "breakpoint #2 at dbg.lcl:8\n"
+    );
+    assert_eq!(
+        run(&mut debugger, "breakpoints"),
+        "#1 dbg.lcl:2\n#2 dbg.lcl:8\n"
+    );
+
+    let out = run(&mut debugger, "continue");
+    assert_eq!(out, "breakpoint #1\ndbg.lcl:2:5\n\t    a 1 +\n\t    ^\n");
+    assert_eq!(run(&mut debugger, "locals"), "a = 3\n");
+    assert_eq!(
+        run(&mut debugger, "backtrace"),
+        "#0 inc at dbg.lcl:2:5\n#1 twice at dbg.lcl:5:7\n#2 <main> at dbg.lcl:7:3\n"
+    );
+
+    // The second call enters the row again
+    assert!(run(&mut debugger, "c").starts_with("breakpoint #1\n"));
+    assert_eq!(run(&mut debugger, "locals"), "a = 4\n");
+    assert!(
+        run(&mut debugger, "bt").starts_with("#0 inc at dbg.lcl:2:5\n#1 twice at dbg.lcl:5:11\n")
+    );
+
+    run(&mut debugger, "delete 1");
+    assert_eq!(run(&mut debugger, "breakpoints"), "#1 dbg.lcl:8\n");
+    let out = run(&mut debugger, "continue");
+    assert_eq!(out, "5\nbreakpoint #1\ndbg.lcl:8:1\n\t5 @r2\n\t^\n");
+
+    run(&mut debugger, "d");
+    assert_eq!(run(&mut debugger, "breakpoints"), "");
+    assert_eq!(run(&mut debugger, "continue"), "program finished\n");
+}
+
+#[test]
+fn debugger_steps_over_and_out_of_calls() {
+    let mut debugger = debugger();
+    assert!(run(&mut debugger, "step").starts_with("dbg.lcl:7:3\n"));
+    assert!(run(&mut debugger, "s").starts_with("dbg.lcl:5:5\n"));
+    assert!(run(&mut debugger, "next").starts_with("dbg.lcl:5:7\n"));
+    assert!(run(&mut debugger, "step").starts_with("dbg.lcl:2:5\n"));
+    assert!(run(&mut debugger, "finish").starts_with("dbg.lcl:5:11\n"));
+    assert_eq!(run(&mut debugger, "stack"), "<1> 4\n");
+    // The second `inc` runs to completion, and with it `twice`
+    assert!(run(&mut debugger, "n").starts_with("dbg.lcl:7:9\n"));
+    assert_eq!(run(&mut debugger, "stack"), "<1> 5\n");
+    assert_eq!(run(&mut debugger, "bt"), "#0 <main> at dbg.lcl:7:9\n");
+    assert_eq!(run(&mut debugger, "f"), "5\nprogram finished\n");
+}
+
+#[test]
+fn debugger_inspects_state_and_restarts() {
+    let mut debugger = debugger();
+    assert_eq!(run(&mut debugger, "c"), "5\nprogram finished\n");
+    assert!(run(&mut debugger, "regs").contains("\nr2 = 5\n"));
+    assert_eq!(run(&mut debugger, "mem mem 2"), "0x400000: 02 01\n");
+    assert_eq!(run(&mut debugger, "mem mem+1 1"), "0x400001: 01\n");
+    assert_eq!(run(&mut debugger, "stack"), "<0>\n");
+    assert_eq!(
+        run(&mut debugger, "step"),
+        "program is not running, use `restart`\n"
+    );
+
+    assert_eq!(
+        run(&mut debugger, "restart"),
+        "dbg.lcl:7:1\n\t3 twice .\n\t^\n"
+    );
+    assert!(run(&mut debugger, "regs").contains("\nr2 = 0\n"));
+    assert_eq!(run(&mut debugger, "mem mem 2"), "0x400000: 00 00\n");
+    assert_eq!(run(&mut debugger, "c"), "5\nprogram finished\n");
+}
+
+#[test]
+fn debugger_reports_runtime_errors() {
+    let mut debugger =
+        Debugger::with_output("err.lcl", "1 .\n+\n".to_string(), Vec::new()).unwrap();
+    let out = run(&mut debugger, "c");
+    assert!(out.starts_with("1\n"), "{}", out);
+    assert!(out.ends_with("\nprogram terminated\n"), "{}", out);
+    assert!(out.contains("err.lcl:2:1"), "{}", out);
+}
+
+#[test]
+fn debugger_rejects_bad_commands() {
+    let mut debugger = debugger();
+    for command in [
+        "break", "break x", "break 0", "delete 1", "mem", "mem mem", "mem x 1",
+    ] {
+        assert!(
+            debugger.command(command).is_err(),
+            "{:?} should fail",
+            command
+        );
+    }
+    let error = debugger.command("frobnicate").unwrap_err();
+    assert!(error.to_string().contains("see `help`"), "{}", error);
+    assert!(!debugger.command("quit").unwrap());
+    assert!(!debugger.command("q").unwrap());
+}
+
+#[test]
+fn memory_parses_addresses() {
+    let memory = Memory::new();
+    let mem = memory.base();
+    assert_eq!(memory.parse_address("mem"), Some(mem));
+    assert_eq!(memory.parse_address("mem+16"), Some(mem + 16));
+    assert_eq!(memory.parse_address("mem+0x10"), Some(mem + 16));
+    assert_eq!(memory.parse_address("42"), Some(42));
+    assert_eq!(memory.parse_address("0x2a"), Some(42));
+    assert_eq!(memory.parse_address("mem16"), None);
+    assert_eq!(memory.parse_address("x"), None);
+}
+
+#[test]
+fn memory_dumps_rows_of_16_bytes() {
+    let mut memory = Memory::new();
+    let mem = memory.base();
+    memory.store(mem + 8, 0x0102).unwrap();
+    assert_eq!(
+        memory.dump(mem + 4, 20).unwrap(),
+        format!(
+            "{:#x}: 00 00 00 00 02 01 00 00 00 00 00 00 00 00 00 00\n{:#x}: 00 00 00 00\n",
+            mem + 4,
+            mem + 20
+        )
+    );
+    assert!(memory.dump(mem - 1, 2).is_err());
+}
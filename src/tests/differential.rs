@@ -0,0 +1,180 @@
+//! Runs programs through both the interpreter and the native backend and
+//! checks that they agree on stdout and the exit code.
+//!
+//! The native side needs ld and nasm or GNU as, the C backend a `cc`.
+//! Backends whose tools are missing are skipped, and when `LCL_SKIP_NATIVE`
+//! is set only the interpreter runs. WebAssembly text always runs, in the
+//! interpreter of `wasm`.
+
+#![allow(unused_imports)]
+use std::{
+    env,
+    fs::{read_dir, read_to_string, remove_file},
+    io::ErrorKind,
+    os::unix::process::ExitStatusExt,
+    path::Path,
+    process::Command,
+    str::from_utf8,
+};
+
+use crate::{
+    compiler::{assemble, compile, Assembler, Options, Platform},
+    interpreter::Interpreter,
+    lexer::tokenize,
+};
+
+/// Stdout and exit code of a program
+#[cfg(test)]
+pub type Outcome = (String, i32);
+
+/// Whether `tool` can be run, unless native runs are skipped
+#[cfg(test)]
+fn available(tool: &str, arg: &str) -> bool {
+    if env::var_os("LCL_SKIP_NATIVE").is_some() {
+        return false;
+    }
+    match Command::new(tool).arg(arg).output() {
+        Ok(_) => true,
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => panic!("failed to run {}: {}", tool, e),
+    }
+}
+
+#[cfg(test)]
+pub fn native_available() -> bool {
+    available("nasm", "-v") || available("as", "--version")
+}
+
+#[cfg(test)]
+pub fn c_available() -> bool {
+    available("cc", "--version")
+}
+
+#[cfg(test)]
+pub fn interpret(src: &str, file: &str) -> Outcome {
+    let mut interpreter = Interpreter::new(Vec::new());
+    let code = match tokenize(src, file).and_then(|tokens| interpreter.eval(tokens)) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    (from_utf8(interpreter.output()).unwrap().to_string(), code)
+}
+
+/// Compiles `src` into `outfile`, runs it and removes the build artifacts
+#[cfg(test)]
+pub fn run_native(src: &str, file: &str, outfile: &str, options: &Options) -> Outcome {
+    let tokens = tokenize(src, file).unwrap();
+    let compiled = compile(&tokens, outfile, options);
+    if compiled.is_ok() {
+        let _ = remove_file(format!("{}.o", outfile));
+    }
+    let _ = remove_file(format!("{}.asm", outfile));
+    let _ = remove_file(format!("{}.s", outfile));
+    let _ = remove_file(format!("{}.c", outfile));
+    compiled.unwrap();
+
+    let output = Command::new(outfile).output().unwrap();
+    remove_file(outfile).unwrap();
+
+    // A crashed binary has no exit code, report it like a shell would
+    let code = output
+        .status
+        .code()
+        .unwrap_or_else(|| 128 + output.status.signal().unwrap());
+    (from_utf8(&output.stdout).unwrap().to_string(), code)
+}
+
+/// Outcome of `src` as WebAssembly text
+#[cfg(test)]
+pub fn run_wat(src: &str, file: &str, opt_level: u8) -> Outcome {
+    let tokens = tokenize(src, file).unwrap();
+    let options = Options {
+        opt_level,
+        target: Platform::Wat,
+        ..Default::default()
+    };
+    let (wat, _) = assemble(&tokens, &options).unwrap();
+    super::wasm::run(&wat)
+}
+
+/// Outcome of `src` in the interpreter, asserting that the native binary,
+/// the one built from C and the WebAssembly module behave the same at every
+/// optimization level when they can be built
+#[cfg(test)]
+pub fn differential(src: &str, file: &str, outfile: &str) -> Outcome {
+    differential_on(src, file, outfile, &[Platform::X86_64, Platform::C])
+}
+
+/// Like `differential`, building native code only for `platforms`
+#[cfg(test)]
+fn differential_on(src: &str, file: &str, outfile: &str, platforms: &[Platform]) -> Outcome {
+    let interpreted = interpret(src, file);
+    for opt_level in 0..=2 {
+        assert_eq!(
+            interpreted,
+            run_wat(src, file, opt_level),
+            "interpreter and wasm module (-O{}) disagree on {:?}",
+            opt_level,
+            src
+        );
+    }
+    let targets = [
+        (Platform::X86_64, native_available()),
+        (Platform::C, c_available()),
+    ];
+    let targets = targets
+        .iter()
+        .filter(|(target, available)| *available && platforms.contains(target));
+    for (target, _) in targets {
+        for opt_level in 0..=2 {
+            let options = Options {
+                opt_level,
+                target: *target,
+                assembler: Assembler::detect(),
+                ..Default::default()
+            };
+            let native = run_native(src, file, outfile, &options);
+            assert_eq!(
+                interpreted, native,
+                "interpreter and {:?} binary (-O{}) disagree on {:?}",
+                target, opt_level, src
+            );
+        }
+    }
+    interpreted
+}
+
+/// Examples printing the address of `mem`, which only the interpreter, C
+/// and WebAssembly place at `MEM_BASE`
+#[cfg(test)]
+const ADDRESS_EXAMPLES: [&str; 1] = ["memory"];
+
+#[test]
+fn differential_examples() {
+    let mut examples: Vec<_> = read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lcl"))
+        .collect();
+    examples.sort();
+    assert!(!examples.is_empty(), "no examples found");
+
+    for path in examples {
+        let src = read_to_string(&path).unwrap();
+        let stem = path.file_stem().unwrap().to_str().unwrap();
+        let outfile = Path::new("src/tests").join(format!("test_example_{}", stem));
+
+        let platforms: &[Platform] = if ADDRESS_EXAMPLES.contains(&stem) {
+            &[Platform::C]
+        } else {
+            &[Platform::X86_64, Platform::C]
+        };
+        let (_, code) = differential_on(
+            &src,
+            path.to_str().unwrap(),
+            outfile.to_str().unwrap(),
+            platforms,
+        );
+        assert_eq!(code, 0, "{} failed", path.display());
+    }
+}
@@ -0,0 +1,40 @@
+#![allow(unused_imports)]
+use crate::golden::{bless_comments, diff, discover, expected_lines, run};
+use std::path::Path;
+
+#[test]
+fn golden_expected_lines() {
+    let src = "1 . // expect: 1\n// expect:2\n3 .\n// expect:  3";
+    assert_eq!(expected_lines(src), vec!["1", "2", " 3"]);
+}
+
+#[test]
+fn golden_bless_in_place() {
+    let src = "1 . // expect: 2\n2 .\n// expect: 1\n";
+    assert_eq!(
+        bless_comments(src, "1\n2\n"),
+        "1 . // expect: 1\n2 .\n// expect: 2\n"
+    );
+}
+
+#[test]
+fn golden_bless_appends() {
+    let src = "1 .    // expect: 1\n2 .\n// expect: 3\n3 .\n";
+    assert_eq!(
+        bless_comments(src, "1\n2\n3\n"),
+        "1 .\n2 .\n3 .\n// expect: 1\n// expect: 2\n// expect: 3\n"
+    );
+}
+
+#[test]
+fn golden_diff() {
+    assert_eq!(diff("1\n2\n3\n", "1\n3\n4\n"), "  1\n- 2\n  3\n+ 4\n");
+    assert_eq!(diff("1\n", "2\n"), "- 1\n+ 2\n");
+}
+
+#[test]
+fn golden_suite() {
+    let programs = discover(Path::new("tests/lcl")).unwrap();
+    assert!(!programs.is_empty(), "no programs found");
+    assert_eq!(run(Path::new("tests/lcl"), false, false), 0);
+}
@@ -0,0 +1,250 @@
+#![allow(unused_imports)]
+use super::interpreter_test;
+use crate::interpreter::{trace::Tracer, Interpreter};
+use crate::lexer::{tokenize, tokens::Token};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::str::from_utf8;
+
+// Push/Pop
+interpreter_test!(interpret_push_int, "!1 ." => "1\n");
+interpreter_test!(interpret_registers, "1 @r1 !r1 ." => "1\n");
+interpreter_test!(interpret_all_registers, "1 @r1 2 @r2 3 @r3 4 @r4 !r1 !r2 !r3 !r4 . . . ." => "4\n3\n2\n1\n");
+interpreter_test!(interpret_registers_across_calls, "fn f do !r5 1 + @r5 end 1 @r5 f f !r5 ." => "3\n");
+
+// Arithmetics
+interpreter_test!(interpret_plus, "2 2 + ." => "4\n");
+interpreter_test!(interpret_minus, "5 2 - ." => "3\n");
+
+// Comparison
+interpreter_test!(interpret_less, "1 2 < . 2 1 < ." => "1\n0\n");
+interpreter_test!(interpret_greater, "2 1 > . 1 2 > ." => "1\n0\n");
+interpreter_test!(interpret_equal, "2 2 = . 2 3 = ." => "1\n0\n");
+interpreter_test!(interpret_not_equal, "2 3 != . 2 2 != ." => "1\n0\n");
+
+// Stack manipulation
+interpreter_test!(interpret_stack_manipulation, "1 dup . . 1 2 drop . 1 2 swap . ." => "1\n1\n1\n1\n2\n");
+interpreter_test!(interpret_over_rot, "1 2 over . . . 1 2 3 rot . . ." => "1\n2\n1\n1\n3\n2\n");
+
+// Memory
+interpreter_test!(interpret_memory_1, "mem 1 @ mem ! ." => "1\n");
+interpreter_test!(interpret_memory_2, "mem 8 + 2 @ mem ! . mem 8 + ! ." => "0\n2\n");
+interpreter_test!(interpret_memory_byte, "mem 258 @ mem !u8 . mem 1 + !u8 ." => "2\n1\n");
+
+// Control flow
+interpreter_test!(
+    interpret_if_else,
+    "2 2 = if
+        1 .
+    else
+        2 .
+    end
+    2 2 != if
+        3 .
+    else
+        4 .
+    end" => "1\n4\n"
+);
+interpreter_test!(
+    interpret_nested_while,
+    "0 while dup 2 < do
+        0 while dup 2 < do
+            over . dup .
+            1 +
+        end drop
+        1 +
+    end" => "0\n0\n0\n1\n1\n0\n1\n1\n"
+);
+
+// Functions
+interpreter_test!(
+    interpret_function_with_args,
+    "fn add a b do
+        a b +
+    end
+    3 2 add ." => "5\n"
+);
+interpreter_test!(
+    interpret_function_argument_order,
+    "fn sub a b do
+        a b -
+    end
+    3 2 sub ." => "-1\n"
+);
+interpreter_test!(
+    interpret_function_not_returning,
+    "fn show a do
+        a . 1 2 drop
+    end
+    5 show 7 ." => "5\n7\n"
+);
+interpreter_test!(
+    interpret_function_recursion,
+    "fn countdown n do
+        n 0 > if
+            n . n 1 - countdown
+        end
+    end
+    3 countdown" => "3\n2\n1\n"
+);
+
+// Inline functions
+interpreter_test!(
+    interpret_inline_function,
+    "inline my_swap do
+        @r1 @r2
+        !r1 !r2
+    end
+    1 2 my_swap . ." => "1\n2\n"
+);
+interpreter_test!(
+    interpret_inline_fn_function,
+    "inline fn my_swap do
+        @r1 @r2
+        !r1 !r2
+    end
+    1 2 my_swap . ." => "1\n2\n"
+);
+
+interpreter_test!(interpret_exported_function, "export fn inc a do a 1 + end 2 inc ." => "3\n");
+interpreter_test!(interpret_extern_declaration, "extern fn abort --\n1 ." => "1\n");
+
+interpreter_test!(FAIL: interpret_unexpected_else, "1 2 3 else 1 2 3");
+interpreter_test!(FAIL: interpret_unexpected_do, "1 2 3 do 1 2 3");
+interpreter_test!(FAIL: interpret_unexpected_end_of_block, "1 2 3 end 1 2 3");
+interpreter_test!(FAIL: interpret_not_defined, "asd");
+interpreter_test!(FAIL: interpret_unfinished_function, "fn test do");
+interpreter_test!(FAIL: interpret_extern_call, "extern fn labs x -- r\n1 labs .");
+interpreter_test!(FAIL: interpret_unfinished_block, "1 if 2");
+interpreter_test!(FAIL: interpret_empty_stack, "1 +");
+interpreter_test!(FAIL: interpret_invalid_register, "1 @r9");
+interpreter_test!(FAIL: interpret_memory_out_of_bounds, "0 !");
+interpreter_test!(FAIL: interpret_assert, "1 assert 0 assert");
+interpreter_test!(FAIL: interpret_assert_eq, "2 2 assert_eq 2 3 assert_eq");
+interpreter_test!(FAIL: interpret_function_frame, "fn f do + end 1 2 f");
+interpreter_test!(FAIL: interpret_multiply, "2 3 *");
+interpreter_test!(FAIL: interpret_divide, "fn half a do a 2 / end 4 half .");
+interpreter_test!(FAIL: interpret_mod, "5 3 %");
+
+#[test]
+fn interpret_rejects_unsupported_operators() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    let result = interpreter.eval(tokenize("3 . 4 *", "<test>").unwrap());
+
+    let error = result.unwrap_err();
+    assert_eq!((error.loc.row, error.loc.col), (1, 7));
+    assert!(
+        error.to_string().contains("`*` is not supported"),
+        "{}",
+        error
+    );
+    assert!(interpreter.output().is_empty());
+}
+
+interpreter_test!(interpret_skips_tests, "test \"t\" do 1 . end 2 ." => "2\n");
+
+#[test]
+fn interpret_run_tests() {
+    let src = "fn inc a do a 1 + end
+        test \"passes\" do 1 inc 2 assert_eq 5 @r1 end
+        test \"fails\" do !r1 5 assert_eq end
+        7";
+    let mut interpreter = Interpreter::new(Vec::new());
+    interpreter.load(tokenize(src, "<test>").unwrap()).unwrap();
+
+    let tests = interpreter.tests().to_vec();
+    let names: Vec<&str> = tests.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["passes", "fails"]);
+
+    interpreter.run_test(&tests[0]).unwrap();
+    // Registers are reset between tests
+    let error = interpreter.run_test(&tests[1]).unwrap_err();
+    assert_eq!((error.loc.row, error.loc.col), (3, 31));
+    assert_eq!(error.error.to_string(), "AssertionError: expected 5, got 0");
+}
+
+#[test]
+fn interpret_atomic_rollback() {
+    let mut interpreter = Interpreter::new(Vec::new());
+    interpreter
+        .eval_atomic(tokenize("1 2 mem 3 @ 4 @r1", "<test>").unwrap())
+        .unwrap();
+
+    let failing = "5 swap drop mem 6 @ mem 8 + 7 @u8 8 @r1 fn f do 1 end 1 + + + +";
+    let result = interpreter.eval_atomic(tokenize(failing, "<test>").unwrap());
+
+    let error = result.unwrap_err();
+    assert_eq!((error.loc.row, error.loc.col), (1, 61));
+    assert_eq!(interpreter.stack(), &[1, 2]);
+    let mem = interpreter.memory().base();
+    assert_eq!(interpreter.memory().load(mem).unwrap(), 3);
+    assert_eq!(interpreter.memory().load(mem + 8).unwrap(), 0);
+    assert_eq!(interpreter.registers()[0], 4);
+    assert!(!interpreter.functions().contains_key("f"));
+
+    interpreter
+        .eval_atomic(tokenize("+ .", "<test>").unwrap())
+        .unwrap();
+    assert_eq!(from_utf8(interpreter.output()).unwrap(), "3\n");
+}
+
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn trace(src: &str, functions: &[&str]) -> String {
+    let buffer = SharedBuffer::default();
+    let functions = functions.iter().map(|f| f.to_string()).collect();
+    let mut interpreter = Interpreter::new(Vec::new());
+    interpreter.set_tracer(Tracer::new(Box::new(buffer.clone()), functions));
+    interpreter.eval(tokenize(src, "<test>").unwrap()).unwrap();
+
+    let log = buffer.0.borrow();
+    from_utf8(&log).unwrap().to_string()
+}
+
+#[test]
+fn interpret_trace() {
+    assert_eq!(
+        trace("1 2 + 3 4 5 6 @r1 .", &[]),
+        "<test>:1:1\t1\t<1> 1\n\
+         <test>:1:3\t2\t<2> 1 2\n\
+         <test>:1:5\t+\t<1> 3\n\
+         <test>:1:7\t3\t<2> 3 3\n\
+         <test>:1:9\t4\t<3> 3 3 4\n\
+         <test>:1:11\t5\t<4> 3 3 4 5\n\
+         <test>:1:13\t6\t<5> ... 3 4 5 6\n\
+         <test>:1:15\t@r1\t<4> 3 3 4 5\n\
+         <test>:1:19\t.\t<3> 3 3 4\n"
+    );
+}
+
+#[test]
+fn interpret_trace_function_filter() {
+    let src = "fn inc a do a 1 + end fn twice a do a inc inc end 1 inc 2 twice drop drop";
+    assert_eq!(
+        trace(src, &["twice"]),
+        "<test>:1:37\ta\t<2> 2 2\n\
+         <test>:1:39\tinc\t<1> 2\n\
+         <test>:1:13\ta\t<2> 2 2\n\
+         <test>:1:15\t1\t<3> 2 2 1\n\
+         <test>:1:17\t+\t<2> 2 3\n\
+         <test>:1:43\tinc\t<1> 2\n\
+         <test>:1:13\ta\t<2> 2 3\n\
+         <test>:1:15\t1\t<3> 2 3 1\n\
+         <test>:1:17\t+\t<2> 2 4\n"
+    );
+}
@@ -0,0 +1,488 @@
+#![allow(unused_imports)]
+use crate::ir::{
+    fold::ConstantFold,
+    inline::{Decision, Inline, MAX_OPS},
+    lower,
+    pass::{Pass, PassManager},
+    tail::TailCalls,
+    BinOp, Module, Op, StackOp,
+};
+use crate::lexer::tokenize;
+
+#[cfg(test)]
+fn lower_src(src: &str) -> Module {
+    lower(tokenize(src, "<test>").unwrap()).unwrap().0
+}
+
+#[test]
+fn lower_arithmetic() {
+    let module = lower_src("2 3 + dup .");
+    assert_eq!(
+        module.main,
+        vec![
+            Op::PushConst(2),
+            Op::PushConst(3),
+            Op::BinOp(BinOp::Add),
+            Op::Stack(StackOp::Dup),
+            Op::Print,
+        ]
+    );
+}
+
+#[test]
+fn lower_if_else() {
+    let module = lower_src("1 if 2 . else 3 . end");
+    assert_eq!(
+        module.main,
+        vec![
+            Op::PushConst(1),
+            Op::Branch(1),
+            Op::PushConst(2),
+            Op::Print,
+            Op::Jump(2),
+            Op::Label(1),
+            Op::PushConst(3),
+            Op::Print,
+            Op::Label(2),
+        ]
+    );
+}
+
+#[test]
+fn lower_while() {
+    let module = lower_src("while 1 do 2 . end");
+    assert_eq!(
+        module.main,
+        vec![
+            Op::Label(1),
+            Op::PushConst(1),
+            Op::Branch(2),
+            Op::PushConst(2),
+            Op::Print,
+            Op::Jump(1),
+            Op::Label(2),
+        ]
+    );
+}
+
+#[test]
+fn lower_functions() {
+    let module = lower_src("fn sub a b do b a - end 3 1 sub .");
+    assert_eq!(module.functions.len(), 1);
+    assert_eq!(module.functions[0].params, 2);
+    assert!(module.functions[0].returning);
+    assert_eq!(
+        module.functions[0].body,
+        vec![Op::PushLocal(1), Op::PushLocal(0), Op::BinOp(BinOp::Sub)]
+    );
+    assert_eq!(
+        module.main[2],
+        Op::Call {
+            name: "sub".to_string(),
+            args: 2,
+            returning: true,
+        }
+    );
+}
+
+#[test]
+fn lower_inline_expands() {
+    let module = lower_src("inline two do 2 end two two + .");
+    assert!(module.functions.is_empty());
+    assert_eq!(
+        module.main,
+        vec![
+            Op::PushConst(2),
+            Op::PushConst(2),
+            Op::BinOp(BinOp::Add),
+            Op::Print,
+        ]
+    );
+}
+
+#[test]
+fn lower_inline_params_read_arguments() {
+    let module = lower_src("inline sub a b do a b - end 5 3 sub .");
+    assert_eq!(
+        module.main,
+        vec![
+            Op::PushConst(5),
+            Op::PushConst(3),
+            Op::Pick(0),
+            Op::Pick(2),
+            Op::BinOp(BinOp::Sub),
+            Op::Stack(StackOp::Swap),
+            Op::Stack(StackOp::Drop),
+            Op::Stack(StackOp::Swap),
+            Op::Stack(StackOp::Drop),
+            Op::Print,
+        ]
+    );
+}
+
+#[test]
+fn lower_unbound_inline_params() {
+    for src in [
+        "inline addto x do x + end 1 2 addto",
+        "inline three x do x x x end 1 three",
+        "inline grow x do while x do x end end 1 grow",
+    ] {
+        let tokens = tokenize(src, "<test>").unwrap();
+        assert!(lower(tokens).is_err(), "{:?} should be an error", src);
+    }
+}
+
+#[test]
+fn lower_leaves_out_tests() {
+    let module = lower_src("1 .\ntest \"one\" do 1 assert end");
+    assert_eq!(module.main, vec![Op::PushConst(1), Op::Print]);
+}
+
+#[test]
+fn lower_recursive_inline() {
+    let tokens = tokenize("inline f do f end f", "<test>").unwrap();
+    assert!(lower(tokens).is_err());
+}
+
+#[test]
+fn lower_extern_calls() {
+    let module = lower_src(
+        "extern fn labs x -- r
+        extern fn abort --
+        extern fn unused x --
+        fn f x do x labs end
+        5 f . abort",
+    );
+    assert_eq!(module.externs, ["labs", "abort"]);
+    assert!(module.functions[0].returning);
+    assert_eq!(
+        module.functions[0].body,
+        vec![
+            Op::PushLocal(0),
+            Op::CallExtern {
+                name: "labs".to_string(),
+                args: 1,
+                returning: true,
+            },
+        ]
+    );
+    assert_eq!(
+        module.main[3],
+        Op::CallExtern {
+            name: "abort".to_string(),
+            args: 0,
+            returning: false,
+        }
+    );
+}
+
+#[test]
+fn lower_bad_extern_signatures() {
+    for src in [
+        "extern fn f a b",
+        "extern fn f a 1 -- r",
+        "extern fn f -- a b",
+        "extern fn f a b c d e f g -- r",
+        "extern f -- r",
+    ] {
+        let tokens = tokenize(src, "<test>").unwrap();
+        assert!(lower(tokens).is_err(), "{:?} should be an error", src);
+    }
+}
+
+#[test]
+fn lower_unsupported_operators() {
+    for (src, col) in [
+        ("1 2 *", 5),
+        ("fn f a do a 2 / end 4 f .", 15),
+        ("3 2 %", 5),
+    ] {
+        let error = match lower(tokenize(src, "<test>").unwrap()) {
+            Ok(_) => panic!("{:?} should be an error", src),
+            Err(e) => e,
+        };
+        assert_eq!((error.loc.row, error.loc.col), (1, col), "{:?}", src);
+    }
+}
+
+#[test]
+fn lower_undefined() {
+    let tokens = tokenize("nothing", "<test>").unwrap();
+    assert!(lower(tokens).is_err());
+}
+
+#[test]
+fn display_module() {
+    let module = lower_src("fn one do 1 end one 0 if . end");
+    assert_eq!(
+        module.to_string(),
+        "fn one params=0 returning\n\
+         \tpush_const 1\n\
+         \n\
+         main\n\
+         \tcall one args=0 returning\n\
+         \tpush_const 0\n\
+         \tbranch L1\n\
+         \tprint\n\
+         L1:\n"
+    );
+}
+
+#[cfg(test)]
+struct Negate;
+
+#[cfg(test)]
+impl Pass for Negate {
+    fn run(&mut self, module: &mut Module) {
+        for op in module.main.iter_mut() {
+            if let Op::PushConst(v) = op {
+                *v = -*v;
+            }
+        }
+    }
+}
+
+#[test]
+fn pass_manager_runs_passes_in_order() {
+    let mut module = lower_src("1 .");
+    let mut passes = PassManager::new();
+    passes.add(Negate).add(Negate).add(Negate);
+    passes.run(&mut module);
+    assert_eq!(module.main, vec![Op::PushConst(-1), Op::Print]);
+}
+
+#[cfg(test)]
+fn fold_src(src: &str) -> Vec<Op> {
+    let mut module = lower_src(src);
+    let mut passes = PassManager::new();
+    passes.add(ConstantFold);
+    passes.run(&mut module);
+    module.main
+}
+
+#[test]
+fn fold_arithmetic() {
+    assert_eq!(fold_src("2 2 + 5 - ."), vec![Op::PushConst(-1), Op::Print]);
+}
+
+#[test]
+fn fold_comparison() {
+    assert_eq!(
+        fold_src("1 2 < 3 3 != ."),
+        vec![Op::PushConst(1), Op::PushConst(0), Op::Print]
+    );
+}
+
+#[test]
+fn fold_stack_manipulation() {
+    assert_eq!(
+        fold_src("1 2 3 rot swap over drop dup"),
+        vec![
+            Op::PushConst(2),
+            Op::PushConst(1),
+            Op::PushConst(3),
+            Op::PushConst(3)
+        ]
+    );
+}
+
+#[test]
+fn fold_constant_branch() {
+    assert_eq!(
+        fold_src("0 if 1 . end"),
+        vec![Op::Jump(1), Op::PushConst(1), Op::Print, Op::Label(1)]
+    );
+    assert_eq!(
+        fold_src("1 if 1 . end"),
+        vec![Op::PushConst(1), Op::Print, Op::Label(1)]
+    );
+}
+
+#[test]
+fn fold_stops_at_labels() {
+    let ops = fold_src("1 while dup 3 < do 1 + end");
+    assert_eq!(
+        ops[..3],
+        [Op::PushConst(1), Op::Label(1), Op::Stack(StackOp::Dup)]
+    );
+}
+
+#[cfg(test)]
+fn warnings(src: &str) -> Vec<String> {
+    let (_, warnings) = lower(tokenize(src, "<test>").unwrap()).unwrap();
+    warnings.iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn lower_drops_unreachable_functions() {
+    let module = lower_src(
+        "fn c do 3 end
+        fn a do 1 . end
+        fn b do a end
+        fn unused do c end
+        b",
+    );
+    let names: Vec<&str> = module.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+}
+
+#[test]
+fn lower_keeps_exported_functions() {
+    let module = lower_src("fn a do 1 end export fn b x do x a + end fn unused do 2 end");
+    let names: Vec<&str> = module.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    assert!(!module.functions[0].export);
+    assert!(module.functions[1].export);
+}
+
+#[test]
+fn lower_keeps_functions_used_by_inline() {
+    let module = lower_src("fn f do 1 . end inline g do f end g");
+    assert_eq!(module.functions.len(), 1);
+}
+
+#[test]
+fn warn_unused_functions() {
+    assert_eq!(
+        warnings("fn used do end\nfn unused do used end\ninline twice do end\nused"),
+        [
+            "UnusedWarning: function unused is never used at <test>:2:1",
+            "UnusedWarning: function twice is never used at <test>:3:1",
+        ]
+    );
+}
+
+#[test]
+fn warn_unused_params() {
+    assert_eq!(
+        warnings("fn f a b do b end 1 2 f"),
+        ["UnusedWarning: parameter a of f is never used at <test>:1:1"]
+    );
+}
+
+#[test]
+fn warn_nothing_for_functions_used_by_tests() {
+    let src = "fn double a do a a + end\ntest \"double\" do 2 double 4 assert_eq end";
+    assert!(warnings(src).is_empty());
+    assert!(lower_src(src).functions.is_empty());
+}
+
+#[test]
+fn warn_nothing_for_replaced_functions() {
+    assert!(warnings("fn f do 1 end fn f do 2 end f .").is_empty());
+}
+
+#[cfg(test)]
+fn tail_calls_src(src: &str) -> Module {
+    let mut module = lower_src(src);
+    let mut passes = PassManager::new();
+    passes.add(TailCalls);
+    passes.run(&mut module);
+    module
+}
+
+#[test]
+fn tail_calls_replace_calls_in_tail_position() {
+    let module = tail_calls_src("fn f n do n 0 > if n 1 - f end end 3 f");
+    let calls: Vec<&Op> = module.functions[0]
+        .body
+        .iter()
+        .filter(|op| matches!(op, Op::Call { .. } | Op::TailCall { .. }))
+        .collect();
+    assert_eq!(
+        calls,
+        [&Op::TailCall {
+            name: "f".to_string(),
+            args: 1
+        }]
+    );
+    assert!(matches!(module.main.last(), Some(Op::Call { .. })));
+}
+
+#[test]
+fn tail_calls_keep_other_calls() {
+    // `g` takes fewer arguments than `f`, `h` is followed by a print
+    let module = tail_calls_src("fn g do end fn h a do end fn f a b do a h g 1 . end 1 2 f");
+    let f = module.functions.iter().find(|f| f.name == "f").unwrap();
+    assert!(!f.body.iter().any(|op| matches!(op, Op::TailCall { .. })));
+}
+
+#[cfg(test)]
+fn inline_src(src: &str) -> Module {
+    let mut module = lower_src(src);
+    let mut passes = PassManager::new();
+    passes.add(Inline);
+    passes.run(&mut module);
+    module
+}
+
+#[test]
+fn inline_small_functions() {
+    let module = inline_src("fn sub a b do a b - end 5 3 sub .");
+    assert!(module.functions.is_empty());
+    assert_eq!(
+        module.main,
+        vec![
+            Op::PushConst(5),
+            Op::PushConst(3),
+            Op::Pick(0),
+            Op::Pick(2),
+            Op::BinOp(BinOp::Sub),
+            Op::Stack(StackOp::Swap),
+            Op::Stack(StackOp::Drop),
+            Op::Stack(StackOp::Swap),
+            Op::Stack(StackOp::Drop),
+            Op::Print,
+        ]
+    );
+}
+
+#[test]
+fn inline_renames_labels() {
+    let module = inline_src("fn f a do a if 1 . end end 0 f 1 f");
+    let labels: Vec<&Op> = module
+        .main
+        .iter()
+        .filter(|op| matches!(op, Op::Label(_)))
+        .collect();
+    assert_eq!(labels.len(), 2);
+    assert_ne!(labels[0], labels[1]);
+}
+
+#[test]
+fn inline_decisions() {
+    let long = " 1 drop".repeat(MAX_OPS);
+    let src = format!(
+        "fn small do 1 end
+        fn countdown n do n if n 1 - countdown end end
+        noinline fn kept do 2 end
+        fn long do{} end
+        fn uneven n do n while 1 do 1 end end
+        small countdown kept long uneven",
+        long
+    );
+    let module = inline_src(&src);
+    assert_eq!(
+        module.inlining,
+        vec![
+            ("small".to_string(), Decision::Inlined),
+            ("countdown".to_string(), Decision::Recursive),
+            ("kept".to_string(), Decision::NoInline),
+            ("long".to_string(), Decision::TooLarge(MAX_OPS * 2)),
+            ("uneven".to_string(), Decision::UnknownDepth),
+        ]
+    );
+    let names: Vec<&str> = module.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["countdown", "kept", "long", "uneven"]);
+}
+
+#[test]
+fn inline_keeps_exported_functions() {
+    let module = inline_src("export fn one do 1 end one .");
+    assert_eq!(
+        module.inlining,
+        vec![("one".to_string(), Decision::Inlined)]
+    );
+    assert_eq!(module.main, vec![Op::PushConst(1), Op::Print]);
+    assert_eq!(module.functions.len(), 1);
+}
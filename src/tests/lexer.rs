@@ -2,7 +2,7 @@
 use super::lexer_test;
 use crate::lexer::{
     tokenize,
-    tokens::{TargetType, TokenType},
+    tokens::{TargetType, TokenType, Width},
 };
 use std::str::FromStr;
 
@@ -23,6 +23,10 @@ lexer_test!(tokenize_do, "do" => vec![TokenType::Do]);
 lexer_test!(tokenize_mem, "mem" => vec![TokenType::Mem]);
 lexer_test!(tokenize_fn, "fn" => vec![TokenType::Function]);
 lexer_test!(tokenize_inline, "inline" => vec![TokenType::Inline]);
+lexer_test!(tokenize_noinline, "noinline fn" => vec![TokenType::NoInline, TokenType::Function]);
+lexer_test!(tokenize_extern, "extern fn" => vec![TokenType::Extern, TokenType::Function]);
+lexer_test!(tokenize_export, "export fn" => vec![TokenType::Export, TokenType::Function]);
+lexer_test!(tokenize_returns, "--" => vec![TokenType::Returns]);
 
 // Push/Pop tests
 lexer_test!(tokenize_pop_to_memory, "@" => vec![TokenType::Pop(TargetType::Memory)]);
@@ -30,6 +34,19 @@ lexer_test!(tokenize_push_from_memory, "!" => vec![TokenType::Push(TargetType::M
 lexer_test!(tokenize_push_integer, "!1" => vec![TokenType::Push(TargetType::Integer(1))]);
 lexer_test!(tokenize_pop_to_register, "@r1" => vec![TokenType::Pop(TargetType::Regsiter(1))]);
 lexer_test!(tokenize_push_from_register, "!r1" => vec![TokenType::Push(TargetType::Regsiter(1))]);
+lexer_test!(tokenize_push_from_memory_sized, "!u8 !i8 !u16 !i16 !u32 !i32" => vec![
+    TokenType::Push(TargetType::SizedMemory(Width::U8)),
+    TokenType::Push(TargetType::SizedMemory(Width::I8)),
+    TokenType::Push(TargetType::SizedMemory(Width::U16)),
+    TokenType::Push(TargetType::SizedMemory(Width::I16)),
+    TokenType::Push(TargetType::SizedMemory(Width::U32)),
+    TokenType::Push(TargetType::SizedMemory(Width::I32)),
+]);
+lexer_test!(tokenize_pop_to_memory_sized, "@u8 @i16 @u32" => vec![
+    TokenType::Pop(TargetType::SizedMemory(Width::U8)),
+    TokenType::Pop(TargetType::SizedMemory(Width::I16)),
+    TokenType::Pop(TargetType::SizedMemory(Width::U32)),
+]);
 
 // Identifiers tests
 lexer_test!(tokenize_single_word_identifier, "test" => vec![TokenType::Identifier("test".to_string())]);
@@ -100,6 +117,13 @@ lexer_test!(tokenize_memory_program, "mem 1 @ mem ! ." => vec![
     TokenType::Dot,
 ]);
 
+lexer_test!(tokenize_test_block, "test \"adds // numbers\" do end" => vec![
+    TokenType::Identifier("test".to_string()),
+    TokenType::Str("adds // numbers".to_string()),
+    TokenType::Do,
+    TokenType::End,
+]);
+
 // Comments tests
 lexer_test!(tokenize_single_line_comment1, "12 // comment" => vec![TokenType::Push(TargetType::Integer(12))]);
 lexer_test!(tokenize_single_line_comment2, "12 /* comment */" => vec![TokenType::Push(TargetType::Integer(12))]);
@@ -117,3 +141,14 @@ lexer_test!(FAIL: tokenize_inavlid_pop_register_1, "@ra1");
 lexer_test!(FAIL: tokenize_inavlid_pop_register_2, "@r1a");
 lexer_test!(FAIL: tokenize_inavlid_push_register_1, "!ra1");
 lexer_test!(FAIL: tokenize_inavlid_push_register_2, "!r1a");
+lexer_test!(FAIL: tokenize_invalid_push_width, "!u64");
+lexer_test!(FAIL: tokenize_invalid_pop_width, "@i7");
+lexer_test!(FAIL: tokenize_unterminated_string, "test \"name\ndo end");
+
+// Locations tests
+#[test]
+fn tokenize_locations_after_newlines() {
+    let tokens = tokenize("1\n   2 /* a\nb */ 3", "<test>").unwrap();
+    let locs: Vec<(usize, usize)> = tokens.iter().map(|t| (t.loc.row, t.loc.col)).collect();
+    assert_eq!(locs, [(1, 1), (2, 4), (3, 6)]);
+}
@@ -0,0 +1,47 @@
+#![allow(unused_imports)]
+use crate::interpreter::memory::Memory;
+use crate::lexer::tokens::Width;
+use crate::lib::constants::MEM_CAPACITY;
+
+#[test]
+fn memory_is_byte_addressed() {
+    let mut memory = Memory::new();
+    let mem = memory.base();
+
+    memory.store(mem, 1).unwrap();
+    memory.store(mem + 8, 2).unwrap();
+
+    assert_eq!(memory.load(mem).unwrap(), 1);
+    assert_eq!(memory.load(mem + 8).unwrap(), 2);
+    assert_eq!(memory.load(mem + 1).unwrap(), 2 << 56);
+}
+
+#[test]
+fn memory_sized_access_is_little_endian() {
+    let mut memory = Memory::new();
+    let mem = memory.base();
+
+    memory.store(mem, 258).unwrap();
+    assert_eq!(memory.load_sized(mem, Width::U8).unwrap(), 2);
+    assert_eq!(memory.load_sized(mem + 1, Width::U8).unwrap(), 1);
+
+    memory.store_sized(mem, -1, Width::U16).unwrap();
+    assert_eq!(memory.load_sized(mem, Width::U16).unwrap(), 65535);
+    assert_eq!(memory.load_sized(mem, Width::I16).unwrap(), -1);
+    assert_eq!(memory.load_sized(mem, Width::I32).unwrap(), 65535);
+}
+
+#[test]
+fn memory_out_of_bounds() {
+    let mut memory = Memory::new();
+    let mem = memory.base();
+    let last = mem + (MEM_CAPACITY * 8) as i64 - 8;
+
+    assert!(memory.load(0).is_err());
+    assert!(memory.load(mem - 1).is_err());
+    assert!(memory.load(last).is_ok());
+    assert!(memory.load(last + 1).is_err());
+    assert!(memory.store(i64::MAX, 1).is_err());
+    assert!(memory.store_sized(last + 7, 1, Width::U8).is_ok());
+    assert!(memory.store_sized(last + 7, 1, Width::U16).is_err());
+}
@@ -1,5 +1,15 @@
+pub mod asm;
 pub mod compiler;
+pub mod debugger;
+pub mod differential;
+pub mod golden;
+pub mod interpreter;
+pub mod ir;
 pub mod lexer;
+pub mod memory;
+pub mod repl;
+#[cfg(test)]
+pub mod wasm;
 
 macro_rules! lexer_test {
     (FAIL: $name:ident, $src:expr) => {
@@ -29,6 +39,8 @@ macro_rules! lexer_test {
     };
 }
 
+/// Compiles the program and checks it against the interpreter, see
+/// `differential` for how the native side is skipped
 macro_rules! compiler_test {
     (FAIL: $name:ident, $src:expr) => {
         #[cfg(test)]
@@ -37,11 +49,19 @@ macro_rules! compiler_test {
             let src: &str = $src;
             let outfile = format!("src/tests/test_{}", stringify!($name));
 
-            let mut tokens: Vec<Token> = tokenize(src, "<test>").unwrap();
-            let result = compile(&mut tokens, &outfile);
+            let tokens: Vec<Token> = tokenize(src, "<test>").unwrap();
+            let options = Options {
+                assembler: Assembler::detect(),
+                ..Default::default()
+            };
+            let result = compile(&tokens, &outfile, &options);
 
             assert!(result.is_err(), "{:?} should be an error", result);
-            remove_file(format!("{}.asm", &outfile)).unwrap();
+            // Nothing is written when the program doesn't build
+            for ext in ["asm", "s"] {
+                assert!(remove_file(format!("{}.{}", &outfile, ext)).is_err());
+            }
+            assert_ne!(interpret(src, "<test>").1, 0, "{:?} should fail", src);
         }
     };
     ($name:ident, $src:expr => $should_be:expr) => {
@@ -52,14 +72,38 @@ macro_rules! compiler_test {
             let should_be = $should_be;
             let outfile = format!("src/tests/test_{}", stringify!($name));
 
-            let mut tokens: Vec<Token> = tokenize(src, "<test>").unwrap();
-            compile(&mut tokens, &outfile).unwrap();
-            let output = Command::new(&outfile).output().unwrap();
-            let result = from_utf8(&output.stdout).unwrap();
+            let (result, code) = differential(src, "<test>", &outfile);
 
-            remove_file(&outfile).unwrap();
-            remove_file(format!("{}.o", &outfile)).unwrap();
-            remove_file(format!("{}.asm", &outfile)).unwrap();
+            assert_eq!(code, 0, "Input was {:?}", src);
+            assert_eq!(result, should_be, "Input was {:?}", src);
+        }
+    };
+}
+
+macro_rules! interpreter_test {
+    (FAIL: $name:ident, $src:expr) => {
+        #[cfg(test)]
+        #[test]
+        fn $name() {
+            let src: &str = $src;
+
+            let mut interpreter = Interpreter::new(Vec::new());
+            let result = tokenize(src, "<test>").and_then(|tokens| interpreter.eval(tokens));
+
+            assert!(result.is_err(), "{:?} should be an error", result);
+        }
+    };
+    ($name:ident, $src:expr => $should_be:expr) => {
+        #[cfg(test)]
+        #[test]
+        fn $name() {
+            let src: &str = $src;
+            let should_be = $should_be;
+
+            let mut interpreter = Interpreter::new(Vec::new());
+            let tokens: Vec<Token> = tokenize(src, "<test>").unwrap();
+            interpreter.eval(tokens).unwrap();
+            let result = from_utf8(interpreter.output()).unwrap();
 
             assert_eq!(result, should_be, "Input was {:?}", src);
         }
@@ -67,4 +111,5 @@ macro_rules! compiler_test {
 }
 
 pub(crate) use compiler_test;
+pub(crate) use interpreter_test;
 pub(crate) use lexer_test;
@@ -0,0 +1,113 @@
+#![allow(unused_imports)]
+use crate::interpreter::program::nesting;
+use crate::lexer::tokenize;
+use crate::repl::{completion::ReplHelper, Repl};
+use rustyline::{completion::Completer, history::History, Context};
+use std::str::from_utf8;
+
+#[cfg(test)]
+fn repl() -> Repl<Vec<u8>> {
+    Repl::with_output(">> ", Vec::new())
+}
+
+#[cfg(test)]
+fn output(repl: &mut Repl<Vec<u8>>) -> String {
+    let out = from_utf8(repl.output()).unwrap().to_string();
+    repl.output().clear();
+    out
+}
+
+#[test]
+fn repl_stack_command() {
+    let mut repl = repl();
+    repl.evaluate("1 2 3\n", "<test>").unwrap();
+    assert!(repl.command(":stack").unwrap());
+    assert_eq!(output(&mut repl), "<3> 1 2 3\n");
+}
+
+#[test]
+fn repl_mem_command() {
+    let mut repl = repl();
+    repl.evaluate("mem 8 + 258 @\n", "<test>").unwrap();
+    repl.command(":mem mem+8 4").unwrap();
+    assert_eq!(output(&mut repl), "0x400008: 02 01 00 00\n");
+    assert!(repl.command(":mem 0 4").is_err());
+    assert!(repl.command(":mem mem").is_err());
+}
+
+#[test]
+fn repl_regs_command() {
+    let mut repl = repl();
+    repl.evaluate("7 @r2 8 @r8\n", "<test>").unwrap();
+    repl.command(":regs").unwrap();
+    assert_eq!(
+        output(&mut repl),
+        "r1 = 0\nr2 = 7\nr3 = 0\nr4 = 0\nr5 = 0\nr6 = 0\nr7 = 0\nr8 = 8\n"
+    );
+}
+
+#[test]
+fn repl_words_and_reset_commands() {
+    let mut repl = repl();
+    repl.evaluate(
+        "fn add a b do a b + end\ninline one do 1 end\nnoinline fn keep do end\n",
+        "<test>",
+    )
+    .unwrap();
+    repl.command(":words").unwrap();
+    assert_eq!(
+        output(&mut repl),
+        "fn add a b\nnoinline fn keep\ninline one\n"
+    );
+
+    repl.command(":reset").unwrap();
+    repl.command(":words").unwrap();
+    repl.command(":stack").unwrap();
+    assert_eq!(output(&mut repl), "<0>\n");
+    assert!(repl.evaluate("1 2 add\n", "<test>").is_err());
+}
+
+#[test]
+fn repl_asm_command() {
+    let mut repl = repl();
+    repl.command(":asm 1 .").unwrap();
+    assert_eq!(
+        output(&mut repl),
+        "\t; Push 1\n\tmov  rax, 1\n\tpush rax\n\t; Dot\n\tpop  rdi\n\tcall print\n"
+    );
+}
+
+#[test]
+fn repl_quit_and_unknown_commands() {
+    let mut repl = repl();
+    assert!(!repl.command(":quit").unwrap());
+    assert!(repl.command(":bogus").is_err());
+}
+
+#[test]
+fn repl_completion() {
+    let helper = ReplHelper {
+        words: vec!["double".to_string(), "square".to_string()],
+    };
+    let history = History::new();
+    let ctx = Context::new(&history);
+
+    let (start, candidates) = helper.complete("1 d", 3, &ctx).unwrap();
+    assert_eq!(start, 2);
+    assert_eq!(candidates, vec!["do", "double", "drop", "dup"]);
+
+    let (start, candidates) = helper.complete(":s", 2, &ctx).unwrap();
+    assert_eq!(start, 0);
+    assert_eq!(candidates, vec![":stack", ":save"]);
+}
+
+#[test]
+fn repl_waits_for_unfinished_definitions() {
+    let depth = |src| nesting(&tokenize(src, "<stdin>").unwrap());
+    assert_eq!(depth("fn f do 1 if"), 2);
+    assert_eq!(depth("fn f do 1 end"), 0);
+    assert_eq!(depth("extern fn labs x -- r"), 0);
+    assert_eq!(depth("extern fn labs x -- r\nfn f do"), 1);
+    assert_eq!(depth("inline fn f do 1"), 1);
+    assert_eq!(depth("inline fn f do 1 end"), 0);
+}
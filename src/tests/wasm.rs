@@ -0,0 +1,378 @@
+//! Interpreter for the WebAssembly text the `wat` target emits, so its
+//! output can be checked without a wasm runtime.
+//!
+//! Only the subset the backend uses is understood: one instruction per
+//! line in the flat syntax, `i32` and `i64` values, and the host functions
+//! `env.print` and `env.memory_error`. Names and branch targets are
+//! resolved before running.
+
+use std::collections::HashMap;
+
+use super::differential::Outcome;
+
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32Or,
+    I64Add,
+    I64Sub,
+    I64LtS,
+    I64GtS,
+    I64Eq,
+    I64Ne,
+}
+
+#[derive(Debug, Clone)]
+enum Instr {
+    Const(i64),
+    GlobalGet(usize),
+    GlobalSet(usize),
+    LocalGet(usize),
+    LocalSet(usize),
+    Print,
+    MemoryError,
+    Call(usize),
+    ReturnCall(usize),
+    Return,
+    /// `block`, `loop` and `end`, branches already know where they go
+    Nop,
+    /// Skips to the index after its `end` when the condition is zero
+    If(usize),
+    Br(usize),
+    BrTable(Vec<usize>),
+    Drop,
+    Unreachable,
+    /// Load with its size in bytes and whether it sign extends
+    Load(usize, bool),
+    /// Store with its size in bytes
+    Store(usize),
+    Eqz,
+    /// `i64.extend_i32_u` and `i32.wrap_i64`, values are kept as `i64`
+    Zext,
+    Num(Num),
+}
+
+#[derive(Debug, Default)]
+struct Func {
+    params: usize,
+    locals: usize,
+    body: Vec<Instr>,
+}
+
+struct Module {
+    funcs: Vec<Func>,
+    main: usize,
+    globals: Vec<i64>,
+    pages: usize,
+}
+
+/// Function as written, before names are resolved
+#[derive(Default)]
+struct Source<'a> {
+    name: &'a str,
+    locals: Vec<&'a str>,
+    params: usize,
+    lines: Vec<&'a str>,
+}
+
+/// Names declared by `(param $x T)` or `(local $x T)` groups on `line`
+fn declared<'a>(line: &'a str, kind: &str) -> Vec<&'a str> {
+    line.split('(')
+        .filter_map(|group| group.strip_prefix(kind))
+        .map(|rest| rest.split_whitespace().next().unwrap())
+        .collect()
+}
+
+fn num(op: &str) -> Num {
+    match op {
+        "i32.add" => Num::I32Add,
+        "i32.sub" => Num::I32Sub,
+        "i32.mul" => Num::I32Mul,
+        "i32.or" => Num::I32Or,
+        "i64.add" => Num::I64Add,
+        "i64.sub" => Num::I64Sub,
+        "i64.lt_s" => Num::I64LtS,
+        "i64.gt_s" => Num::I64GtS,
+        "i64.eq" => Num::I64Eq,
+        "i64.ne" => Num::I64Ne,
+        _ => panic!("unknown instruction {}", op),
+    }
+}
+
+/// Instructions of `source` with names replaced by indices
+fn resolve(
+    source: &Source,
+    funcs: &HashMap<&str, usize>,
+    globals: &HashMap<&str, usize>,
+) -> Vec<Instr> {
+    let local = |name: &str| source.locals.iter().position(|l| *l == name).unwrap();
+
+    // Names of the open blocks and where they start, branches remember the
+    // start of their target and are patched once every `end` is known
+    let mut open: Vec<&str> = Vec::new();
+    let mut ends: HashMap<usize, usize> = HashMap::new();
+    let mut starts: Vec<usize> = Vec::new();
+    let mut branches: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut body = Vec::new();
+    for (i, line) in source.lines.iter().enumerate() {
+        let mut words = line.split_whitespace();
+        let op = words.next().unwrap();
+        let arg = words.next().unwrap_or("");
+        // Start of the innermost open block named `name`
+        let target = |open: &[&str], starts: &[usize], name: &str| {
+            starts[open.iter().rposition(|n| *n == name).unwrap()]
+        };
+        body.push(match op {
+            "i32.const" | "i64.const" => Instr::Const(arg.parse().unwrap()),
+            "global.get" => Instr::GlobalGet(globals[arg]),
+            "global.set" => Instr::GlobalSet(globals[arg]),
+            "local.get" => Instr::LocalGet(local(arg)),
+            "local.set" => Instr::LocalSet(local(arg)),
+            "call" if arg == "$print" => Instr::Print,
+            "call" if arg == "$memory_error" => Instr::MemoryError,
+            "call" => Instr::Call(funcs[arg]),
+            "return_call" => Instr::ReturnCall(funcs[arg]),
+            "return" => Instr::Return,
+            "block" | "loop" | "if" => {
+                open.push(arg);
+                starts.push(i);
+                match op {
+                    "if" => Instr::If(0),
+                    _ => Instr::Nop,
+                }
+            }
+            "end" => {
+                open.pop();
+                ends.insert(starts.pop().unwrap(), i + 1);
+                Instr::Nop
+            }
+            "br" => {
+                branches.push((i, vec![target(&open, &starts, arg)]));
+                Instr::Br(0)
+            }
+            "br_table" => {
+                let targets = line
+                    .split_whitespace()
+                    .skip(1)
+                    .map(|name| target(&open, &starts, name))
+                    .collect();
+                branches.push((i, targets));
+                Instr::BrTable(Vec::new())
+            }
+            "drop" => Instr::Drop,
+            "unreachable" => Instr::Unreachable,
+            "i64.load" => Instr::Load(8, false),
+            "i64.load8_u" => Instr::Load(1, false),
+            "i64.load8_s" => Instr::Load(1, true),
+            "i64.load16_u" => Instr::Load(2, false),
+            "i64.load16_s" => Instr::Load(2, true),
+            "i64.load32_u" => Instr::Load(4, false),
+            "i64.load32_s" => Instr::Load(4, true),
+            "i64.store" => Instr::Store(8),
+            "i64.store8" => Instr::Store(1),
+            "i64.store16" => Instr::Store(2),
+            "i64.store32" => Instr::Store(4),
+            "i64.eqz" => Instr::Eqz,
+            "i64.extend_i32_u" | "i32.wrap_i64" => Instr::Zext,
+            _ => Instr::Num(num(op)),
+        });
+    }
+
+    for (start, end) in ends.iter() {
+        if let Instr::If(skip) = &mut body[*start] {
+            *skip = *end;
+        }
+    }
+    // Loops start over, blocks continue after their `end`
+    let destination = |start: usize| match source.lines[start].starts_with("loop") {
+        true => start + 1,
+        false => ends[&start],
+    };
+    for (at, targets) in branches {
+        body[at] = match &body[at] {
+            Instr::Br(_) => Instr::Br(destination(targets[0])),
+            _ => Instr::BrTable(targets.into_iter().map(destination).collect()),
+        };
+    }
+    body
+}
+
+fn parse(wat: &str) -> Module {
+    let mut sources: Vec<Source> = Vec::new();
+    let mut globals: HashMap<&str, usize> = HashMap::new();
+    let mut initial = Vec::new();
+    let mut pages = 0;
+    let mut inside = false;
+    for line in wat.lines().map(str::trim) {
+        if inside {
+            let source = sources.last_mut().unwrap();
+            if line == ")" {
+                inside = false;
+            } else if line.starts_with("(local") {
+                source.locals.extend(declared(line, "local "));
+            } else {
+                source.lines.push(line);
+            }
+        } else if let Some(rest) = line.strip_prefix("(func ") {
+            let name = match rest.strip_prefix("(export \"") {
+                Some(export) => export.split('"').next().unwrap(),
+                None => rest.split_whitespace().next().unwrap(),
+            };
+            let params = declared(line, "param ");
+            sources.push(Source {
+                name,
+                params: params.len(),
+                locals: params,
+                lines: Vec::new(),
+            });
+            inside = true;
+        } else if let Some(rest) = line.strip_prefix("(global ") {
+            let name = rest.split_whitespace().next().unwrap();
+            let init = rest.rsplit(' ').next().unwrap().trim_end_matches(')');
+            globals.insert(name, globals.len());
+            initial.push(init.parse().unwrap());
+        } else if let Some(rest) = line.strip_prefix("(memory (export \"memory\") ") {
+            pages = rest.trim_end_matches(')').parse().unwrap();
+        }
+    }
+
+    let funcs: HashMap<&str, usize> = sources
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.name, i))
+        .collect();
+    Module {
+        funcs: sources
+            .iter()
+            .map(|s| Func {
+                params: s.params,
+                locals: s.locals.len(),
+                body: resolve(s, &funcs, &globals),
+            })
+            .collect(),
+        main: funcs["main"],
+        globals: initial,
+        pages,
+    }
+}
+
+struct Frame {
+    func: usize,
+    pc: usize,
+    locals: Vec<i64>,
+}
+
+fn frame(module: &Module, func: usize, stack: &mut Vec<i64>) -> Frame {
+    let f = &module.funcs[func];
+    let mut locals = vec![0; f.locals];
+    for i in (0..f.params).rev() {
+        locals[i] = stack.pop().unwrap();
+    }
+    Frame {
+        func,
+        pc: 0,
+        locals,
+    }
+}
+
+fn numeric(op: Num, a: i64, b: i64) -> i64 {
+    let (x, y) = (a as i32, b as i32);
+    match op {
+        Num::I32Add => x.wrapping_add(y) as u32 as i64,
+        Num::I32Sub => x.wrapping_sub(y) as u32 as i64,
+        Num::I32Mul => x.wrapping_mul(y) as u32 as i64,
+        Num::I32Or => (x | y) as u32 as i64,
+        Num::I64Add => a.wrapping_add(b),
+        Num::I64Sub => a.wrapping_sub(b),
+        Num::I64LtS => (a < b) as i64,
+        Num::I64GtS => (a > b) as i64,
+        Num::I64Eq => (a == b) as i64,
+        Num::I64Ne => (a != b) as i64,
+    }
+}
+
+/// Runs the exported `main` of `wat`, returns what it printed and `1` if
+/// it trapped
+pub fn run(wat: &str) -> Outcome {
+    let module = parse(wat);
+    let mut globals = module.globals.clone();
+    let mut memory = vec![0u8; module.pages * 65536];
+    let mut stack: Vec<i64> = Vec::new();
+    let mut output = String::new();
+
+    let mut frames = vec![frame(&module, module.main, &mut stack)];
+    while let Some(current) = frames.last_mut() {
+        let Some(instr) = module.funcs[current.func].body.get(current.pc) else {
+            frames.pop();
+            continue;
+        };
+        current.pc += 1;
+        match instr {
+            Instr::Const(v) => stack.push(*v),
+            Instr::GlobalGet(g) => stack.push(globals[*g]),
+            Instr::GlobalSet(g) => globals[*g] = stack.pop().unwrap(),
+            Instr::LocalGet(l) => stack.push(current.locals[*l]),
+            Instr::LocalSet(l) => current.locals[*l] = stack.pop().unwrap(),
+            Instr::Print => output.push_str(&format!("{}\n", stack.pop().unwrap())),
+            Instr::MemoryError | Instr::Unreachable => return (output, 1),
+            Instr::Call(f) => {
+                let callee = frame(&module, *f, &mut stack);
+                frames.push(callee);
+            }
+            Instr::ReturnCall(f) => {
+                frames.pop();
+                let callee = frame(&module, *f, &mut stack);
+                frames.push(callee);
+            }
+            Instr::Return => {
+                frames.pop();
+            }
+            Instr::Nop => {}
+            Instr::If(skip) => {
+                if stack.pop().unwrap() as i32 == 0 {
+                    current.pc = *skip;
+                }
+            }
+            Instr::Br(to) => current.pc = *to,
+            Instr::BrTable(targets) => {
+                let i = stack.pop().unwrap() as u32 as usize;
+                current.pc = targets[i.min(targets.len() - 1)];
+            }
+            Instr::Drop => {
+                stack.pop();
+            }
+            Instr::Load(size, signed) => {
+                let addr = stack.pop().unwrap() as u32 as usize;
+                let mut bytes = [0u8; 8];
+                bytes[..*size].copy_from_slice(&memory[addr..addr + size]);
+                let v = i64::from_le_bytes(bytes);
+                let shift = 64 - size * 8;
+                stack.push(match signed {
+                    true => (v << shift) >> shift,
+                    false => v,
+                });
+            }
+            Instr::Store(size) => {
+                let v = stack.pop().unwrap();
+                let addr = stack.pop().unwrap() as u32 as usize;
+                memory[addr..addr + size].copy_from_slice(&v.to_le_bytes()[..*size]);
+            }
+            Instr::Eqz => {
+                let v = stack.pop().unwrap();
+                stack.push((v == 0) as i64);
+            }
+            Instr::Zext => {
+                let v = stack.pop().unwrap();
+                stack.push(v as u32 as i64);
+            }
+            Instr::Num(op) => {
+                let b = stack.pop().unwrap();
+                let a = stack.pop().unwrap();
+                stack.push(numeric(*op, a, b));
+            }
+        }
+    }
+    (output, 0)
+}
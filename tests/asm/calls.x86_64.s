@@ -0,0 +1,173 @@
+	.intel_syntax noprefix
+	.global _start
+	.text
+print:
+	sub     rsp, 40
+	mov     rsi, rdi
+	mov     r11, rdi
+	test    rsi, rsi
+	jns     .Lprint_unsigned
+	neg     rsi
+.Lprint_unsigned:
+	mov     r10, -3689348814741910323
+	mov     BYTE PTR [rsp+39], 10
+	lea     rcx, [rsp+38]
+	lea     r8, [rsp+40]
+.Lprint:
+	mov     rax, rsi
+	mov     r9, r8
+	mul     r10
+	mov     rax, rsi
+	sub     r9, rcx
+	shr     rdx, 3
+	lea     rdi, [rdx+rdx*4]
+	add     rdi, rdi
+	sub     rax, rdi
+	add     eax, 48
+	mov     BYTE PTR [rcx], al
+	mov     rax, rsi
+	mov     rsi, rdx
+	mov     rdx, rcx
+	sub     rcx, 1
+	cmp     rax, 9
+	ja      .Lprint
+	test    r11, r11
+	jns     .Lprint_positive
+	sub     rdx, 1
+	mov     BYTE PTR [rdx], 45
+	add     r9, 1
+.Lprint_positive:
+	sub     rdx, r8
+	mov     edi, 1
+	xor     eax, eax
+	lea     rsi, [rsp+40+rdx]
+	mov     rdx, r9
+	mov     rax, 1
+	syscall
+	add     rsp, 40
+	ret
+add3:
+	push rbp
+	mov rbp, rsp
+	# Push local 0
+	mov rax, [rbp + 16]
+	push rax
+	# Push local 1
+	mov rax, [rbp + 24]
+	push rax
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	# Push local 2
+	mov rax, [rbp + 32]
+	push rax
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+
+	pop rax
+	mov rsp, rbp
+	pop rbp
+	ret
+
+countdown:
+	push rbp
+	mov rbp, rsp
+	# Push local 0
+	mov rax, [rbp + 16]
+	push rax
+	# Push 0
+	mov  rax, 0
+	push rax
+	# Greater
+	mov rcx, 0
+	mov rdx, 1
+	pop rbx
+	pop rax
+	cmp rax, rbx
+	cmovg rcx, rdx
+	push rcx
+	# Branch
+	pop rax
+	test rax, rax
+	jz .L1
+	# Push local 0
+	mov rax, [rbp + 16]
+	push rax
+	# Push 1
+	mov  rax, 1
+	push rax
+	# Minus
+	pop  rax
+	pop  rbx
+	sub  rbx, rax
+	push rbx
+	# Tail call countdown
+	mov rax, [rsp + 0]
+	mov [rbp + 16], rax
+	mov rsp, rbp
+	pop rbp
+	jmp countdown
+.L1:
+	mov rsp, rbp
+	pop rbp
+	ret
+
+_start:
+	xor r12, r12
+	xor r13, r13
+	xor r14, r14
+	xor r15, r15
+	# Push 1
+	mov  rax, 1
+	push rax
+	# Push 2
+	mov  rax, 2
+	push rax
+	# Push 3
+	mov  rax, 3
+	push rax
+	# Call add3
+	call add3
+	add rsp, 24
+	push rax
+	# Dot
+	pop  rdi
+	call print
+	# Push 5
+	mov  rax, 5
+	push rax
+	# Call countdown
+	call countdown
+	add rsp, 8
+	# Push 7
+	mov  rax, 7
+	push rax
+	# Pop r12
+	pop r12
+	# Push r12
+	push r12
+	# Push r12
+	push r12
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	# Dot
+	pop  rdi
+	call print
+
+	mov rax, 60
+	mov rdi, 0
+	syscall
+	ret
+	.bss
+mem:
+	.skip 2097152
+regs:
+	.skip 32
@@ -0,0 +1,150 @@
+	.global _start
+	.text
+print:
+	sub     sp, sp, #32
+	mov     x6, x0
+	cmp     x0, #0
+	cneg    x0, x0, lt
+	add     x1, sp, #31
+	mov     w2, #10
+	strb    w2, [x1]
+	mov     x3, #10
+.Lprint:
+	udiv    x4, x0, x3
+	msub    x5, x4, x3, x0
+	add     w5, w5, #48
+	strb    w5, [x1, #-1]!
+	mov     x0, x4
+	cbnz    x0, .Lprint
+	tbz     x6, #63, .Lprint_positive
+	mov     w5, #45
+	strb    w5, [x1, #-1]!
+.Lprint_positive:
+	add     x2, sp, #32
+	sub     x2, x2, x1
+	mov     x0, #1
+	mov     x8, #64
+	svc     #0
+	add     sp, sp, #32
+	ret
+_start:
+	adrp x28, stack_top
+	add x28, x28, :lo12:stack_top
+	mov x19, #0
+	mov x20, #0
+	mov x21, #0
+	mov x22, #0
+	mov x23, #0
+	mov x24, #0
+	mov x25, #0
+	mov x26, #0
+	// Push 0
+	movz x0, #0
+	str x0, [x28, #-8]!
+.L1:
+	// DUP
+	ldr x0, [x28]
+	str x0, [x28, #-8]!
+	// Push 3
+	movz x0, #3
+	str x0, [x28, #-8]!
+	// Less
+	ldr x1, [x28], #8
+	ldr x0, [x28], #8
+	cmp x0, x1
+	cset x0, lt
+	str x0, [x28, #-8]!
+	// Branch
+	ldr x0, [x28], #8
+	cbz x0, .L2
+	// MEM
+	adrp x0, mem
+	add x0, x0, :lo12:mem
+	str x0, [x28, #-8]!
+	// OVER
+	ldr x0, [x28, #8]
+	str x0, [x28, #-8]!
+	// Plus
+	ldr x1, [x28], #8
+	ldr x0, [x28], #8
+	add x0, x0, x1
+	str x0, [x28, #-8]!
+	// OVER
+	ldr x0, [x28, #8]
+	str x0, [x28, #-8]!
+	// Store
+	ldr x0, [x28], #8
+	ldr x1, [x28], #8
+	strb w0, [x1]
+	// Push 1
+	movz x0, #1
+	str x0, [x28, #-8]!
+	// Plus
+	ldr x1, [x28], #8
+	ldr x0, [x28], #8
+	add x0, x0, x1
+	str x0, [x28, #-8]!
+	b .L1
+.L2:
+	// DROP
+	add x28, x28, #8
+	// MEM
+	adrp x0, mem
+	add x0, x0, :lo12:mem
+	str x0, [x28, #-8]!
+	// Load
+	ldr x0, [x28], #8
+	ldrh w0, [x0]
+	str x0, [x28, #-8]!
+	// Dot
+	ldr x0, [x28], #8
+	bl print
+	// MEM
+	adrp x0, mem
+	add x0, x0, :lo12:mem
+	str x0, [x28, #-8]!
+	// Push 8
+	movz x0, #8
+	str x0, [x28, #-8]!
+	// Plus
+	ldr x1, [x28], #8
+	ldr x0, [x28], #8
+	add x0, x0, x1
+	str x0, [x28, #-8]!
+	// Push 300
+	movz x0, #300
+	str x0, [x28, #-8]!
+	// Store
+	ldr x0, [x28], #8
+	ldr x1, [x28], #8
+	str w0, [x1]
+	// MEM
+	adrp x0, mem
+	add x0, x0, :lo12:mem
+	str x0, [x28, #-8]!
+	// Push 8
+	movz x0, #8
+	str x0, [x28, #-8]!
+	// Plus
+	ldr x1, [x28], #8
+	ldr x0, [x28], #8
+	add x0, x0, x1
+	str x0, [x28, #-8]!
+	// Load
+	ldr x0, [x28], #8
+	ldrsw x0, [x0]
+	str x0, [x28, #-8]!
+	// Dot
+	ldr x0, [x28], #8
+	bl print
+
+	mov x0, #0
+	mov x8, #93
+	svc #0
+	.bss
+	.balign 16
+stack:
+	.skip 8388608
+stack_top:
+mem:
+	.skip 2097152
@@ -0,0 +1,165 @@
+	.intel_syntax noprefix
+	.global _start
+	.text
+print:
+	sub     rsp, 40
+	mov     rsi, rdi
+	mov     r11, rdi
+	test    rsi, rsi
+	jns     .Lprint_unsigned
+	neg     rsi
+.Lprint_unsigned:
+	mov     r10, -3689348814741910323
+	mov     BYTE PTR [rsp+39], 10
+	lea     rcx, [rsp+38]
+	lea     r8, [rsp+40]
+.Lprint:
+	mov     rax, rsi
+	mov     r9, r8
+	mul     r10
+	mov     rax, rsi
+	sub     r9, rcx
+	shr     rdx, 3
+	lea     rdi, [rdx+rdx*4]
+	add     rdi, rdi
+	sub     rax, rdi
+	add     eax, 48
+	mov     BYTE PTR [rcx], al
+	mov     rax, rsi
+	mov     rsi, rdx
+	mov     rdx, rcx
+	sub     rcx, 1
+	cmp     rax, 9
+	ja      .Lprint
+	test    r11, r11
+	jns     .Lprint_positive
+	sub     rdx, 1
+	mov     BYTE PTR [rdx], 45
+	add     r9, 1
+.Lprint_positive:
+	sub     rdx, r8
+	mov     edi, 1
+	xor     eax, eax
+	lea     rsi, [rsp+40+rdx]
+	mov     rdx, r9
+	mov     rax, 1
+	syscall
+	add     rsp, 40
+	ret
+_start:
+	xor r12, r12
+	xor r13, r13
+	xor r14, r14
+	xor r15, r15
+	# Push 0
+	mov  rax, 0
+	push rax
+.L1:
+	# DUP
+	pop rax
+	push rax
+	push rax
+	# Push 3
+	mov  rax, 3
+	push rax
+	# Less
+	mov rcx, 0
+	mov rdx, 1
+	pop rbx
+	pop rax
+	cmp rax, rbx
+	cmovl rcx, rdx
+	push rcx
+	# Branch
+	pop rax
+	test rax, rax
+	jz .L2
+	# MEM
+	push OFFSET mem
+	# OVER
+	pop rax
+	pop rbx
+	push rbx
+	push rax
+	push rbx
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	# OVER
+	pop rax
+	pop rbx
+	push rbx
+	push rax
+	push rbx
+	# Store U8
+	pop rax
+	pop rbx
+	mov [rbx], al
+	# Push 1
+	mov  rax, 1
+	push rax
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	jmp .L1
+.L2:
+	# DROP
+	pop rax
+	xor rax, rax
+	# MEM
+	push OFFSET mem
+	# Load U16
+	pop rax
+	movzx rbx, WORD PTR [rax]
+	push rbx
+	# Dot
+	pop  rdi
+	call print
+	# MEM
+	push OFFSET mem
+	# Push 8
+	mov  rax, 8
+	push rax
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	# Push 300
+	mov  rax, 300
+	push rax
+	# Store I32
+	pop rax
+	pop rbx
+	mov [rbx], eax
+	# MEM
+	push OFFSET mem
+	# Push 8
+	mov  rax, 8
+	push rax
+	# Plus
+	pop  rax
+	pop  rbx
+	add  rax, rbx
+	push rax
+	# Load I32
+	pop rax
+	movsxd rbx, DWORD PTR [rax]
+	push rbx
+	# Dot
+	pop  rdi
+	call print
+
+	mov rax, 60
+	mov rdi, 0
+	syscall
+	ret
+	.bss
+mem:
+	.skip 2097152
+regs:
+	.skip 32